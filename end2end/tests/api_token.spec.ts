@@ -0,0 +1,94 @@
+import { test, expect, type Page } from "@playwright/test";
+import { SITE, register, uniqueEmail } from "./helpers";
+
+async function createProfile(page: Page): Promise<string> {
+  const username = `t${Date.now().toString(36)}`;
+  await page.goto(`${SITE}/settings/profiles`);
+  await page.getByLabel("Username").fill(username);
+  await page.getByRole("button", { name: "Create profile" }).click();
+  await expect(page.getByText("Profile created.")).toBeVisible();
+  return username;
+}
+
+async function createToken(page: Page, name: string): Promise<string> {
+  await page.goto(`${SITE}/settings/tokens`);
+  await page.getByLabel("Name").fill(name);
+  await page.getByRole("button", { name: "Create API token" }).click();
+  const token = page.locator("code", { hasText: "qat_" });
+  await expect(token).toBeVisible();
+  return (await token.textContent()) ?? "";
+}
+
+test("a token acts as its profile until revoked", async ({
+  page,
+  request,
+}) => {
+  await register(page, request, uniqueEmail("token"));
+  const username = await createProfile(page);
+  const token = await createToken(page, "Tally bot");
+  const headers = { Authorization: `Bearer ${token}` };
+
+  const whoami = await request.get(`${SITE}/api/whoami`, { headers });
+  expect(whoami.ok()).toBeTruthy();
+  expect(await whoami.json()).toMatchObject({ username, scopes: ["read"] });
+
+  await page.reload();
+  await expect(page.getByRole("row", { name: /Tally bot/ })).not.toContainText(
+    "never",
+  );
+
+  await page.getByRole("button", { name: "Revoke" }).click();
+  await expect(page.getByText("API token revoked.")).toBeVisible();
+
+  const revoked = await request.get(`${SITE}/api/whoami`, { headers });
+  expect(revoked.ok()).toBeFalsy();
+});
+
+test("a token can't manage the account", async ({ page, request }) => {
+  await register(page, request, uniqueEmail("token-settings"));
+  await createProfile(page);
+  const token = await createToken(page, "Archiver");
+
+  const exported = await request.get(`${SITE}/api/export_account_data`, {
+    headers: { Authorization: `Bearer ${token}` },
+  });
+  expect(exported.ok()).toBeFalsy();
+  expect(await exported.text()).toContain("API tokens can't be used for this");
+});
+
+test("only tokens with the write scope can edit their profile", async ({
+  page,
+  request,
+}) => {
+  await register(page, request, uniqueEmail("token-write"));
+  const username = await createProfile(page);
+  const readOnly = await createToken(page, "Reader");
+
+  await page.goto(`${SITE}/settings/tokens`);
+  await page.getByLabel("Name").fill("Writer");
+  await page.getByLabel(/Write:/).check();
+  await page.getByRole("button", { name: "Create API token" }).click();
+  const writer =
+    (await page.locator("code", { hasText: "qat_" }).textContent()) ?? "";
+
+  const refused = await request.post(`${SITE}/api/edit_current_profile`, {
+    headers: { Authorization: `Bearer ${readOnly}` },
+    form: { display_name: "Renamed by bot" },
+  });
+  expect(refused.ok()).toBeFalsy();
+  expect(await refused.text()).toContain("doesn't have the write scope");
+
+  const edited = await request.post(`${SITE}/api/edit_current_profile`, {
+    headers: { Authorization: `Bearer ${writer}` },
+    form: { display_name: "Renamed by bot" },
+  });
+  expect(edited.ok()).toBeTruthy();
+
+  const whoami = await request.get(`${SITE}/api/whoami`, {
+    headers: { Authorization: `Bearer ${writer}` },
+  });
+  expect(await whoami.json()).toMatchObject({
+    username,
+    display_name: "Renamed by bot",
+  });
+});
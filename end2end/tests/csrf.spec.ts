@@ -0,0 +1,53 @@
+import { test, expect, type Page } from "@playwright/test";
+import { SITE, uniqueEmail } from "./helpers";
+
+/** Submit the email login form, returning the URL of the server function it posts to. */
+async function emailLoginEndpoint(page: Page): Promise<string> {
+  await page.goto(`${SITE}/auth/email`);
+  await page.getByPlaceholder("email").fill(uniqueEmail("csrf"));
+  const posted = page.waitForRequest(
+    (request) => request.method() === "POST" && request.url().includes("/api/"),
+  );
+  await page.getByRole("button", { name: "Email me" }).click();
+  return (await posted).url();
+}
+
+async function csrfCookie(page: Page): Promise<string> {
+  const cookies = await page.context().cookies(SITE);
+  return cookies.find((cookie) => cookie.name === "csrf")?.value ?? "";
+}
+
+test("the site's own forms get through", async ({ page }) => {
+  await emailLoginEndpoint(page);
+  await page.waitForURL(`${SITE}/auth/email/challenge`);
+  expect(await csrfCookie(page)).toHaveLength(32);
+});
+
+test("requests from other sites are refused", async ({ page }) => {
+  const endpoint = await emailLoginEndpoint(page);
+  const token = await csrfCookie(page);
+
+  const forged = await page.request.post(endpoint, {
+    headers: { Origin: "http://evil.example", "x-csrf-token": token },
+    form: { email: uniqueEmail("csrf-forged") },
+  });
+  expect(forged.status()).toBe(403);
+  expect(await forged.text()).toContain("didn't come from this site");
+});
+
+test("requests without the token are refused", async ({ page }) => {
+  const endpoint = await emailLoginEndpoint(page);
+
+  const missing = await page.request.post(endpoint, {
+    headers: { Origin: SITE },
+    form: { email: uniqueEmail("csrf-missing") },
+  });
+  expect(missing.status()).toBe(403);
+  expect(await missing.text()).toContain("security token is missing or expired");
+
+  const wrong = await page.request.post(endpoint, {
+    headers: { Origin: SITE },
+    form: { email: uniqueEmail("csrf-wrong"), csrf_token: "x".repeat(32) },
+  });
+  expect(wrong.status()).toBe(403);
+});
@@ -0,0 +1,76 @@
+import { APIRequestContext, Page, expect } from "@playwright/test";
+
+export const SITE = "http://localhost:3000";
+/** Mailpit collects every email sent in the dev environment. */
+export const MAILPIT = "http://localhost:8025";
+
+/** A fresh email address, so tests don't share accounts. */
+export function uniqueEmail(prefix: string): string {
+  const suffix = `${Date.now()}-${Math.floor(Math.random() * 1e6)}`;
+  return `${prefix}-${suffix}@example.com`;
+}
+
+/** Wait for the newest login code sent to the address and return it. */
+export async function latestLoginCode(
+  request: APIRequestContext,
+  email: string,
+): Promise<string> {
+  let code = "";
+  await expect
+    .poll(async () => {
+      const search = await request.get(`${MAILPIT}/api/v1/search`, {
+        params: { query: `to:"${email}"`, limit: 1 },
+      });
+      const { messages } = await search.json();
+      if (!messages?.length) {
+        return "";
+      }
+      const message = await request.get(
+        `${MAILPIT}/api/v1/message/${messages[0].ID}`,
+      );
+      const { Text } = await message.json();
+      code = Text.match(/<site>\.\s+([A-Za-z0-9]+)\s/)?.[1] ?? "";
+      return code;
+    })
+    .not.toBe("");
+  return code;
+}
+
+/** Log in with an emailed code, ending up at the registration page for new addresses. */
+export async function emailLogin(
+  page: Page,
+  request: APIRequestContext,
+  email: string,
+) {
+  await page.goto(`${SITE}/auth/email`);
+  await page.getByPlaceholder("email").fill(email);
+  await page.getByRole("button", { name: "Email me" }).click();
+  await page.waitForURL(`${SITE}/auth/email/challenge`);
+  await page
+    .getByPlaceholder("response")
+    .fill(await latestLoginCode(request, email));
+  await page.getByRole("button", { name: "Submit code" }).click();
+}
+
+/** Register a new account without a profile, leaving it logged in. */
+export async function register(
+  page: Page,
+  request: APIRequestContext,
+  email: string,
+) {
+  await emailLogin(page, request, email);
+  await page.waitForURL(`${SITE}/auth/register`);
+  await page.getByLabel("Agree to terms of service").check();
+  await page.getByRole("button", { name: "Create account" }).click();
+  await page.waitForURL(`${SITE}/`);
+  await expect(page.getByRole("link", { name: "Log out" })).toBeVisible();
+}
+
+/** Log out of the current session only. */
+export async function logOut(page: Page) {
+  await page.goto(`${SITE}/auth/logout`);
+  await page.getByRole("button", { name: "Log out", exact: true }).click();
+  await expect(
+    page.getByRole("link", { name: "Login/register" }),
+  ).toBeVisible();
+}
@@ -0,0 +1,59 @@
+import { test, expect, type Page } from "@playwright/test";
+import { SITE, logOut, register, uniqueEmail } from "./helpers";
+
+// Log in at the mock provider from docker-compose.yml, which takes any username as the subject.
+async function mockProviderLogin(page: Page, subject: string) {
+  await page.waitForURL(/oidc\.localhost/);
+  await page.locator('input[name="username"]').fill(subject);
+  await page.locator('input[type="submit"]').click();
+}
+
+async function linkMockProvider(page: Page, subject: string) {
+  await page.goto(`${SITE}/settings/logins`);
+  await page.getByRole("button", { name: "Link Mock provider" }).click();
+  await mockProviderLogin(page, subject);
+  await page.waitForURL(`${SITE}/settings/logins`);
+  await expect(page.getByRole("cell", { name: "Mock provider" })).toBeVisible();
+}
+
+async function logInWithMockProvider(page: Page, subject: string) {
+  await page.goto(`${SITE}/auth`);
+  await page
+    .locator("form", { hasText: "Mock provider" })
+    .getByRole("button")
+    .click();
+  await mockProviderLogin(page, subject);
+}
+
+test("link a provider and log in with it", async ({ page, request }) => {
+  const subject = uniqueEmail("oidc");
+  await register(page, request, uniqueEmail("oidc"));
+  await linkMockProvider(page, subject);
+
+  await logOut(page);
+
+  await logInWithMockProvider(page, subject);
+  await page.waitForURL(`${SITE}/`);
+  await expect(page.getByRole("link", { name: "Log out" })).toBeVisible();
+
+  await page.goto(`${SITE}/settings/logins`);
+  await expect(page.getByRole("row", { name: /Mock provider/ })).not.toContainText(
+    "never",
+  );
+});
+
+test("an unlinked identity can't log in", async ({ page, request }) => {
+  const subject = uniqueEmail("oidc-unlinked");
+  await register(page, request, uniqueEmail("oidc-unlinked"));
+  await linkMockProvider(page, subject);
+
+  await page.getByRole("button", { name: "Unlink" }).click();
+  await expect(page.getByText("Login unlinked.")).toBeVisible();
+  await expect(page.getByText("You haven't linked any logins yet.")).toBeVisible();
+
+  await logOut(page);
+
+  await logInWithMockProvider(page, subject);
+  await page.waitForURL(/\/auth\/oidc\/callback/);
+  await expect(page.getByText("isn't linked to any account")).toBeVisible();
+});
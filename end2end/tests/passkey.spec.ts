@@ -0,0 +1,69 @@
+import { test, expect } from "@playwright/test";
+import { SITE, logOut, register, uniqueEmail } from "./helpers";
+
+// Virtual authenticators are only available through the Chrome DevTools Protocol.
+test.skip(
+  ({ browserName }) => browserName !== "chromium",
+  "needs a virtual authenticator",
+);
+
+test.beforeEach(async ({ page }) => {
+  const cdp = await page.context().newCDPSession(page);
+  await cdp.send("WebAuthn.enable");
+  await cdp.send("WebAuthn.addVirtualAuthenticator", {
+    options: {
+      protocol: "ctap2",
+      transport: "internal",
+      hasResidentKey: true,
+      hasUserVerification: true,
+      isUserVerified: true,
+      automaticPresenceSimulation: true,
+    },
+  });
+});
+
+test("add a passkey and log in with it", async ({ page, request }) => {
+  await register(page, request, uniqueEmail("passkey"));
+
+  await page.goto(`${SITE}/settings/passkeys`);
+  await expect(
+    page.getByText("You haven't added any passkeys yet."),
+  ).toBeVisible();
+  await page.getByLabel("Name").fill("Test authenticator");
+  await page.getByRole("button", { name: "Add passkey" }).click();
+  await expect(page.getByText("Passkey added.")).toBeVisible();
+  await expect(
+    page.getByRole("cell", { name: "Test authenticator" }),
+  ).toBeVisible();
+
+  await logOut(page);
+
+  await page.goto(`${SITE}/auth`);
+  await page.getByRole("link", { name: "Passkey" }).click();
+  await page.waitForURL(`${SITE}/auth/passkey`);
+  await page.getByRole("button", { name: "Log in with a passkey" }).click();
+  await page.waitForURL(`${SITE}/`);
+  await expect(page.getByRole("link", { name: "Log out" })).toBeVisible();
+
+  await page.goto(`${SITE}/settings/passkeys`);
+  await expect(
+    page.getByRole("row", { name: /Test authenticator/ }),
+  ).not.toContainText("never");
+});
+
+test("a passkey that was removed can't log in", async ({ page, request }) => {
+  await register(page, request, uniqueEmail("passkey-removed"));
+
+  await page.goto(`${SITE}/settings/passkeys`);
+  await page.getByLabel("Name").fill("Soon gone");
+  await page.getByRole("button", { name: "Add passkey" }).click();
+  await expect(page.getByText("Passkey added.")).toBeVisible();
+  await page.getByRole("button", { name: "Remove" }).click();
+  await expect(page.getByText("Passkey removed.")).toBeVisible();
+
+  await logOut(page);
+
+  await page.goto(`${SITE}/auth/passkey`);
+  await page.getByRole("button", { name: "Log in with a passkey" }).click();
+  await expect(page.getByText("isn't added to any account")).toBeVisible();
+});
@@ -0,0 +1,95 @@
+import { test, expect, type Page } from "@playwright/test";
+import { createHmac } from "node:crypto";
+import { SITE, emailLogin, logOut, register, uniqueEmail } from "./helpers";
+
+const STEP_SEC = 30;
+
+/** Decode an RFC 4648 base32 secret, the way authenticator apps show it. */
+function decodeBase32(secret: string): Buffer {
+  const alphabet = "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+  let bits = "";
+  for (const char of secret.replace(/=+$/, "").toUpperCase()) {
+    bits += alphabet.indexOf(char).toString(2).padStart(5, "0");
+  }
+  const bytes = bits.match(/.{8}/g) ?? [];
+  return Buffer.from(bytes.map((byte) => parseInt(byte, 2)));
+}
+
+/** The code an authenticator app shows for a time step, per RFC 6238 with SHA-1 and 6 digits. */
+function totpCode(secret: string, step: number): string {
+  const counter = Buffer.alloc(8);
+  counter.writeBigUInt64BE(BigInt(step));
+  const hmac = createHmac("sha1", decodeBase32(secret)).update(counter).digest();
+  const offset = hmac[hmac.length - 1] & 0xf;
+  const code = (hmac.readUInt32BE(offset) & 0x7fffffff) % 1_000_000;
+  return code.toString().padStart(6, "0");
+}
+
+function currentStep(): number {
+  return Math.floor(Date.now() / 1000 / STEP_SEC);
+}
+
+/** Turn on an authenticator app, returning its secret and the step of the code used. */
+async function enrol(page: Page): Promise<{ secret: string; step: number }> {
+  await page.goto(`${SITE}/settings/two-factor`);
+  await page
+    .getByRole("button", { name: "Set up an authenticator app" })
+    .click();
+  const secret = (await page.locator("p.font-mono").textContent()) ?? "";
+  expect(secret).not.toBe("");
+
+  const step = currentStep();
+  await page.getByPlaceholder("code", { exact: true }).fill(totpCode(secret, step));
+  await page.getByRole("button", { name: "Turn on" }).click();
+  await expect(page.getByText("Save these recovery codes")).toBeVisible();
+  return { secret, step };
+}
+
+test("email login asks for the authenticator app's code", async ({
+  page,
+  request,
+}) => {
+  const email = uniqueEmail("totp");
+  await register(page, request, email);
+  const { secret, step } = await enrol(page);
+  await logOut(page);
+
+  await emailLogin(page, request, email);
+  await page.waitForURL(`${SITE}/auth/totp`);
+
+  // The code that turned the app on was already used.
+  await page.getByPlaceholder("code").fill(totpCode(secret, step));
+  await page.getByRole("button", { name: "Submit code" }).click();
+  await expect(page.getByText("Code rejected.")).toBeVisible();
+
+  // The next step's code is still within the allowed clock drift.
+  await page.getByPlaceholder("code").fill(totpCode(secret, step + 1));
+  await page.getByRole("button", { name: "Submit code" }).click();
+  await page.waitForURL(`${SITE}/`);
+  await expect(page.getByRole("link", { name: "Log out" })).toBeVisible();
+});
+
+test("a recovery code works once in place of the app", async ({
+  page,
+  request,
+}) => {
+  const email = uniqueEmail("totp-recovery");
+  await register(page, request, email);
+  await enrol(page);
+  const recoveryCode =
+    (await page.locator("ul.font-mono li").first().textContent()) ?? "";
+  await logOut(page);
+
+  await emailLogin(page, request, email);
+  await page.waitForURL(`${SITE}/auth/totp`);
+  await page.getByPlaceholder("code").fill(recoveryCode);
+  await page.getByRole("button", { name: "Submit code" }).click();
+  await page.waitForURL(`${SITE}/`);
+  await logOut(page);
+
+  await emailLogin(page, request, email);
+  await page.waitForURL(`${SITE}/auth/totp`);
+  await page.getByPlaceholder("code").fill(recoveryCode);
+  await page.getByRole("button", { name: "Submit code" }).click();
+  await expect(page.getByText("Code rejected.")).toBeVisible();
+});
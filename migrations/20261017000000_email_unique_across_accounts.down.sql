@@ -0,0 +1,3 @@
+drop index if exists account_secondary_email_idx;
+drop trigger if exists tcheck_account_email_unique on account;
+drop function if exists check_account_email_unique;
@@ -0,0 +1,45 @@
+-- An email may only belong to one account, whether as its primary or a secondary email. A unique
+-- constraint can't see into arrays, so this is checked by a trigger instead.
+create or replace function check_account_email_unique() returns trigger as $$
+  declare
+    new_emails email[] := array_prepend(new.email, coalesce(new.secondary_email, '{}'));
+    duplicate email;
+  begin
+    -- Serialize changes involving the same emails, so concurrent transactions can't both pass.
+    perform pg_advisory_xact_lock(hashtext(lower(e::text)))
+    from unnest(new_emails) as e
+    order by lower(e::text);
+
+    select e into duplicate
+    from unnest(new_emails) as e
+    group by e
+    having count(*) > 1
+    limit 1;
+
+    if duplicate is null then
+      select e into duplicate
+      from unnest(new_emails) as e
+      where exists(
+        select 1
+        from account
+        where id <> new.id
+          and (email = e or secondary_email @> array[e])
+      )
+      limit 1;
+    end if;
+
+    if duplicate is not null then
+      raise exception 'email % is already used', duplicate
+        using errcode = 'unique_violation', constraint = 'email_unique_across_accounts';
+    end if;
+    return new;
+  end;
+$$ language plpgsql;
+
+create trigger tcheck_account_email_unique
+  before insert or update of email, secondary_email on account
+  for each row execute function check_account_email_unique();
+
+-- Secondary emails are looked up on every login and by the trigger above, with `@>` so this
+-- index can be used.
+create index account_secondary_email_idx on account using gin (secondary_email);
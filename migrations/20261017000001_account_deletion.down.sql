@@ -0,0 +1,2 @@
+drop index if exists account_deletion_requested_at_idx;
+alter table account drop column if exists deletion_requested_at;
@@ -0,0 +1,6 @@
+alter table account add column deletion_requested_at timestamp;
+comment on column account.deletion_requested_at is 'When the owner asked for the account to be deleted. Null if they haven''t. The account is deleted for good once the grace period after this is over.';
+
+-- The purge task looks for accounts whose grace period is over.
+create index account_deletion_requested_at_idx on account (deletion_requested_at)
+  where deletion_requested_at is not null;
@@ -0,0 +1,8 @@
+drop trigger if exists trecord_profile_username_deletion on profile;
+drop function if exists record_profile_username_deletion;
+drop trigger if exists trecord_profile_username_change on profile;
+drop function if exists record_profile_username_change;
+drop trigger if exists tcheck_profile_username_not_recently_used on profile;
+drop function if exists check_profile_username_not_recently_used;
+drop function if exists username_recently_used;
+drop table if exists profile_username_history;
@@ -0,0 +1,76 @@
+create table profile_username_history (
+  id uuid primary key default uuid_generate_v7(),
+  profile_id uuid references profile on delete set null,
+  username username not null,
+  changed_at timestamp generated always as (uuid_v7_to_timestamp(id)) stored
+);
+
+comment on table profile_username_history is 'Usernames profiles had before being renamed or deleted. Links using them redirect to the profile until someone else takes them, which nobody can for 90 days, so they can''t be used to impersonate it.';
+comment on column profile_username_history.id is 'History entry ID.';
+comment on column profile_username_history.profile_id is 'Profile that was renamed, or null once it''s deleted. Kept so a deleted profile''s usernames stay reserved like a renamed one''s.';
+comment on column profile_username_history.username is 'Username the profile had before the change.';
+comment on column profile_username_history.changed_at is 'When the profile stopped using the username, by being renamed or deleted.';
+
+create index profile_username_history_username_idx on profile_username_history (username);
+create index profile_username_history_profile_id_idx on profile_username_history (profile_id);
+
+-- Whether a profile other than the given one, including a deleted one, stopped using a username
+-- recently enough that it's still reserved for it.
+create or replace function username_recently_used(name username, except_profile uuid) returns boolean as $$
+  select exists(
+    select 1
+    from profile_username_history
+    where username = name
+      and (profile_id is null or profile_id is distinct from except_profile)
+      and changed_at > now() - interval '90 days'
+  );
+$$ language sql stable;
+
+create or replace function check_profile_username_not_recently_used() returns trigger as $$
+  begin
+    -- Serialize changes involving the same usernames, so a name can't be taken while the rename
+    -- that frees it is still in progress.
+    perform pg_advisory_xact_lock(hashtext('username:' || u))
+    from unnest(array[new.username, old.username]) as u
+    where u is not null
+    order by u;
+
+    if username_recently_used(new.username, new.id) then
+      raise exception 'username % was recently used by another profile', new.username
+        using errcode = 'unique_violation', constraint = 'username_recently_used';
+    end if;
+    return new;
+  end;
+$$ language plpgsql;
+
+create trigger tcheck_profile_username_not_recently_used
+  before insert or update of username on profile
+  for each row execute function check_profile_username_not_recently_used();
+
+create or replace function record_profile_username_change() returns trigger as $$
+  begin
+    insert into profile_username_history (profile_id, username)
+    values (old.id, old.username);
+    return new;
+  end;
+$$ language plpgsql;
+
+create trigger trecord_profile_username_change
+  after update of username on profile
+  for each row
+  when (old.username is distinct from new.username)
+  execute function record_profile_username_change();
+
+create or replace function record_profile_username_deletion() returns trigger as $$
+  begin
+    -- The profile_id is set to null right after, along with the profile's earlier usernames.
+    insert into profile_username_history (profile_id, username)
+    values (old.id, old.username);
+    return old;
+  end;
+$$ language plpgsql;
+
+create trigger trecord_profile_username_deletion
+  before delete on profile
+  for each row
+  execute function record_profile_username_deletion();
@@ -0,0 +1,2 @@
+alter table account drop column if exists is_admin;
+drop table if exists username_rule;
@@ -0,0 +1,40 @@
+create table username_rule (
+  word varchar(20) primary key check ( word ~ '^[a-z0-9]+$' ),
+  blocked boolean not null default false
+);
+
+comment on table username_rule is 'Words that can''t be used as usernames. Usernames are compared after undoing look-alike substitutions, so e.g. 4dm1n counts as admin. Add or remove rows to configure them; admins are exempt.';
+comment on column username_rule.word is 'Word to reserve or block.';
+comment on column username_rule.blocked is 'False to only reserve the word itself, optionally followed by digits. True to block every username containing it.';
+
+insert into username_rule (word) values
+  -- Names that look official.
+  ('admin'),
+  ('administrator'),
+  ('anonymous'),
+  ('help'),
+  ('moderator'),
+  ('official'),
+  ('root'),
+  ('security'),
+  ('staff'),
+  ('support'),
+  ('system'),
+  -- Top-level routes and parts of the site.
+  ('account'),
+  ('api'),
+  ('assets'),
+  ('auth'),
+  ('favicon'),
+  ('login'),
+  ('logout'),
+  ('pkg'),
+  ('profile'),
+  ('register'),
+  ('settings');
+
+insert into username_rule (word, blocked) values
+  ('questarch', true);
+
+alter table account add column is_admin boolean not null default false;
+comment on column account.is_admin is 'Whether the account administers the site. Admins can take reserved and blocked usernames.';
@@ -0,0 +1,20 @@
+create table webauthn_credential (
+  id uuid primary key default uuid_generate_v7(),
+  account_id uuid references account on delete cascade not null,
+  credential_id bytea unique not null,
+  passkey jsonb not null,
+  name varchar(50) not null,
+  created_at timestamp generated always as (uuid_v7_to_timestamp(id)) stored,
+  last_used_at timestamp
+);
+
+comment on table webauthn_credential is 'Passkeys (WebAuthn credentials) that can be used to log in to an account.';
+comment on column webauthn_credential.id is 'Credential entry ID.';
+comment on column webauthn_credential.account_id is 'Account the passkey logs in to.';
+comment on column webauthn_credential.credential_id is 'ID the authenticator gave the credential.';
+comment on column webauthn_credential.passkey is 'Public key and other state of the credential, as serialized by webauthn-rs.';
+comment on column webauthn_credential.name is 'Name the owner gave the passkey, to tell them apart.';
+comment on column webauthn_credential.created_at is 'When the passkey was added.';
+comment on column webauthn_credential.last_used_at is 'When the passkey was last used to log in.';
+
+create index webauthn_credential_account_id_idx on webauthn_credential (account_id);
@@ -0,0 +1,2 @@
+drop table if exists totp_recovery_code;
+drop table if exists totp_credential;
@@ -0,0 +1,27 @@
+create table totp_credential (
+  account_id uuid primary key references account on delete cascade,
+  secret bytea not null,
+  created_at timestamp not null default now(),
+  last_used_step bigint
+);
+
+comment on table totp_credential is 'Authenticator apps (TOTP) set up as a second factor for email login. At most one per account.';
+comment on column totp_credential.account_id is 'Account the second factor protects.';
+comment on column totp_credential.secret is 'Shared secret the codes are generated from.';
+comment on column totp_credential.created_at is 'When the authenticator app was set up.';
+comment on column totp_credential.last_used_step is 'Time step of the last accepted code, so a code can''t be used twice.';
+
+create table totp_recovery_code (
+  id uuid primary key default uuid_generate_v7(),
+  account_id uuid references totp_credential on delete cascade not null,
+  code_hash bytea not null,
+  used_at timestamp
+);
+
+comment on table totp_recovery_code is 'One-time codes to use instead of the authenticator app, e.g. if it was lost.';
+comment on column totp_recovery_code.id is 'Recovery code entry ID.';
+comment on column totp_recovery_code.account_id is 'Account the code is for.';
+comment on column totp_recovery_code.code_hash is 'SHA-256 hash of the code. The codes are random enough that a slow hash isn''t needed.';
+comment on column totp_recovery_code.used_at is 'When the code was used. Used codes don''t work again.';
+
+create index totp_recovery_code_account_id_idx on totp_recovery_code (account_id);
@@ -0,0 +1,23 @@
+create table linked_identity (
+  id uuid primary key default uuid_generate_v7(),
+  account_id uuid references account on delete cascade not null,
+  provider varchar(50) not null,
+  issuer text not null,
+  subject text not null,
+  email text,
+  created_at timestamp generated always as (uuid_v7_to_timestamp(id)) stored,
+  last_used_at timestamp,
+  unique (issuer, subject)
+);
+
+comment on table linked_identity is 'Identities at OpenID Connect providers that can be used to log in to an account.';
+comment on column linked_identity.id is 'Linked identity entry ID.';
+comment on column linked_identity.account_id is 'Account the identity logs in to.';
+comment on column linked_identity.provider is 'ID of the configured provider the identity was linked through, for display.';
+comment on column linked_identity.issuer is 'Issuer of the identity, as in its ID tokens.';
+comment on column linked_identity.subject is 'ID of the identity at its issuer, as in its ID tokens.';
+comment on column linked_identity.email is 'Email the provider gave for the identity when it was linked, if any, to tell identities apart.';
+comment on column linked_identity.created_at is 'When the identity was linked.';
+comment on column linked_identity.last_used_at is 'When the identity was last used to log in.';
+
+create index linked_identity_account_id_idx on linked_identity (account_id);
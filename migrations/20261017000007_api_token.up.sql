@@ -0,0 +1,23 @@
+create table api_token (
+  id uuid primary key default uuid_generate_v7(),
+  account_id uuid references account on delete cascade not null,
+  profile_id uuid references profile on delete cascade not null,
+  name varchar(50) not null,
+  token_hash bytea unique not null,
+  scopes text[] not null,
+  created_at timestamp generated always as (uuid_v7_to_timestamp(id)) stored,
+  last_used_at timestamp
+);
+
+comment on table api_token is 'Personal API tokens that scripts and bots use to act as a profile.';
+comment on column api_token.id is 'Token entry ID.';
+comment on column api_token.account_id is 'Account that owns the token.';
+comment on column api_token.profile_id is 'Profile the token acts as.';
+comment on column api_token.name is 'Name the owner gave the token, to tell them apart.';
+comment on column api_token.token_hash is 'SHA-256 hash of the token. The token itself is only shown once, when created.';
+comment on column api_token.scopes is 'What the token is allowed to do, e.g. read or write.';
+comment on column api_token.created_at is 'When the token was created.';
+comment on column api_token.last_used_at is 'When the token was last used, to within a minute.';
+
+create index api_token_account_id_idx on api_token (account_id);
+create index api_token_profile_id_idx on api_token (profile_id);
@@ -0,0 +1,175 @@
+/// Errors from account and profile server functions, which can point at the form field that
+/// caused them.
+use crate::components::ui::*;
+use crate::components::username::USERNAME_MIN_LEN;
+
+use leptos::prelude::*;
+use leptos::server_fn::ServerFn;
+use std::fmt;
+use std::str::FromStr;
+
+/// An error the user can fix by changing one form field, or any other error.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccountError {
+    EmailTaken,
+    EmailTooLong,
+    EmailInvalid,
+    UsernameTaken,
+    /// Another profile had the username until recently, so it's still reserved for it.
+    UsernameRecentlyUsed,
+    /// The username is on the reserved list.
+    UsernameReserved,
+    /// The username contains a blocked word.
+    UsernameNotAllowed,
+    UsernameTooShort,
+    UsernameInvalid,
+    /// Anything not caused by a single field, with a message for the user.
+    Other(String),
+}
+
+impl AccountError {
+    /// Name of the form input this error is about, if any.
+    pub fn field(&self) -> Option<&'static str> {
+        match self {
+            Self::EmailTaken | Self::EmailTooLong | Self::EmailInvalid => Some("email"),
+            Self::UsernameTaken
+            | Self::UsernameRecentlyUsed
+            | Self::UsernameReserved
+            | Self::UsernameNotAllowed
+            | Self::UsernameTooShort
+            | Self::UsernameInvalid => Some("username"),
+            Self::Other(_) => None,
+        }
+    }
+
+    /// Message to show the user.
+    pub fn message(&self) -> String {
+        match self {
+            Self::EmailTaken => String::from("That email is already used by another account."),
+            Self::EmailTooLong => String::from("Emails can be at most 254 characters long."),
+            Self::EmailInvalid => String::from("That doesn't look like an email address."),
+            Self::UsernameTaken => String::from("That username is taken."),
+            Self::UsernameRecentlyUsed => String::from(
+                "Another profile used that username until recently, so it can't be taken yet.",
+            ),
+            Self::UsernameReserved => String::from("That username is reserved."),
+            Self::UsernameNotAllowed => String::from("That username isn't allowed."),
+            Self::UsernameTooShort => {
+                format!("Usernames must be at least {USERNAME_MIN_LEN} characters long.")
+            }
+            Self::UsernameInvalid => String::from(
+                "Usernames must start with a letter and have only lowercase letters and numbers.",
+            ),
+            Self::Other(message) => message.clone(),
+        }
+    }
+
+    /// Recognize violations of the constraints in the account and profile tables by name, or
+    /// describe any other database error with the given context.
+    #[cfg(feature = "ssr")]
+    pub fn from_db(err: sqlx::Error, context: &str) -> Self {
+        match err.as_database_error().and_then(|err| err.constraint()) {
+            Some("account_email_key" | "email_unique_across_accounts") => Self::EmailTaken,
+            Some("email_not_too_long") => Self::EmailTooLong,
+            Some("email_check") => Self::EmailInvalid,
+            Some("profile_username_key") => Self::UsernameTaken,
+            Some("username_recently_used") => Self::UsernameRecentlyUsed,
+            Some("username_not_too_short") => Self::UsernameTooShort,
+            Some("username_check") => Self::UsernameInvalid,
+            _ => Self::Other(format!("{context}: {err}")),
+        }
+    }
+}
+
+impl From<ServerFnError> for AccountError {
+    fn from(err: ServerFnError) -> Self {
+        Self::Other(err.to_string())
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<fred::error::Error> for AccountError {
+    fn from(err: fred::error::Error) -> Self {
+        ServerFnError::from(err).into()
+    }
+}
+
+// Display and FromStr are how server functions send custom errors to the client, so they must
+// round trip.
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmailTaken => write!(f, "EmailTaken"),
+            Self::EmailTooLong => write!(f, "EmailTooLong"),
+            Self::EmailInvalid => write!(f, "EmailInvalid"),
+            Self::UsernameTaken => write!(f, "UsernameTaken"),
+            Self::UsernameRecentlyUsed => write!(f, "UsernameRecentlyUsed"),
+            Self::UsernameReserved => write!(f, "UsernameReserved"),
+            Self::UsernameNotAllowed => write!(f, "UsernameNotAllowed"),
+            Self::UsernameTooShort => write!(f, "UsernameTooShort"),
+            Self::UsernameInvalid => write!(f, "UsernameInvalid"),
+            Self::Other(message) => write!(f, "Other:{message}"),
+        }
+    }
+}
+
+impl FromStr for AccountError {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "EmailTaken" => Self::EmailTaken,
+            "EmailTooLong" => Self::EmailTooLong,
+            "EmailInvalid" => Self::EmailInvalid,
+            "UsernameTaken" => Self::UsernameTaken,
+            "UsernameRecentlyUsed" => Self::UsernameRecentlyUsed,
+            "UsernameReserved" => Self::UsernameReserved,
+            "UsernameNotAllowed" => Self::UsernameNotAllowed,
+            "UsernameTooShort" => Self::UsernameTooShort,
+            "UsernameInvalid" => Self::UsernameInvalid,
+            _ => Self::Other(s.strip_prefix("Other:").ok_or(())?.to_string()),
+        })
+    }
+}
+
+/// Show the error from an action next to the form input it's about.
+#[component]
+pub fn ShowFieldError<S>(action: ServerAction<S>, field: &'static str) -> impl IntoView
+where
+    S: ServerFn<Error = AccountError> + Send + Sync + Clone + 'static,
+    S::Output: Clone + Send + Sync + 'static,
+{
+    move || match action.value().get() {
+        Some(Err(ServerFnError::WrappedServerError(err))) if err.field() == Some(field) => {
+            Some(view! { <p class="text-red-700">{err.message()}</p> })
+        }
+        _ => None,
+    }
+}
+
+/// Like ShowActionStatus, but leaves errors about a specific form input to ShowFieldError.
+#[component]
+pub fn ShowFormStatus<S>(
+    action: ServerAction<S>,
+    #[prop(optional)] success: &'static str,
+) -> impl IntoView
+where
+    S: ServerFn<Error = AccountError> + Send + Sync + Clone + 'static,
+    S::Output: Clone + Send + Sync + 'static,
+{
+    view! {
+        <Show when=move || { !action.pending().get() } fallback=move || view! { <Spinner /> }>
+            {move || match action.value().get() {
+                Some(Err(ServerFnError::WrappedServerError(err))) => {
+                    match err.field() {
+                        Some(_) => view! { "Please fix the problems above." }.into_any(),
+                        None => view! { <ShowError error=err.message() /> }.into_any(),
+                    }
+                }
+                Some(Err(err)) => view! { <ShowError error=err.to_string() /> }.into_any(),
+                Some(Ok(_)) => view! { {success} }.into_any(),
+                None => view! { "" }.into_any(),
+            }}
+        </Show>
+    }
+}
@@ -6,6 +6,9 @@ use leptos_router::{
 };
 
 use crate::components::auth::AuthRoutes;
+use crate::components::public_profile::PublicProfileRoutes;
+use crate::components::session::*;
+use crate::components::settings::SettingsRoutes;
 use crate::components::ui::*;
 
 #[component]
@@ -23,26 +26,74 @@ pub fn App() -> impl IntoView {
 
         // content for this welcome page
         <Router>
-            <nav class="flex gap-2 justify-start">
-                <div>
-                    <ANorm href="/">Home</ANorm>
-                </div>
-                <div>
-                    <ANorm href="/auth">Login/register</ANorm>
-                </div>
-            </nav>
-            <main>
-                <Routes fallback=move || "Not found.">
-                    <Route path=StaticSegment("") view=HomePage />
-                    <AuthRoutes />
-                    <Route path=WildcardSegment("any") view=NotFound />
-                </Routes>
-                <Body {..} class="p-4 mx-auto max-w-7xl" />
-            </main>
+            <CurrentSessionProvider>
+                <Nav />
+                <main>
+                    <Routes fallback=move || "Not found.">
+                        <Route path=StaticSegment("") view=HomePage />
+                        <AuthRoutes />
+                        <SettingsRoutes />
+                        <PublicProfileRoutes />
+                        <Route path=WildcardSegment("any") view=NotFound />
+                    </Routes>
+                    <Body {..} class="p-4 mx-auto max-w-7xl" />
+                </main>
+            </CurrentSessionProvider>
         </Router>
     }
 }
 
+/// Navigation bar, which depends on who is logged in.
+#[component]
+fn Nav() -> impl IntoView {
+    let session = use_current_session();
+
+    view! {
+        <nav class="flex gap-2 justify-start">
+            <div>
+                <ANorm href="/">Home</ANorm>
+            </div>
+            <Transition>
+                {move || Suspend::new(async move {
+                    match session.await {
+                        Ok(Some(session)) => {
+                            let name = if session.reader_mode {
+                                String::from("Reader mode")
+                            } else if session.display_name.is_empty() {
+                                format!("@{}", session.username)
+                            } else {
+                                format!("{} (@{})", session.display_name, session.username)
+                            };
+                            view! {
+                                <div class="font-bold">{name}</div>
+                                <div>
+                                    <ANorm href="/auth/profile">Switch profile</ANorm>
+                                </div>
+                                <div>
+                                    <ANorm href="/settings">Settings</ANorm>
+                                </div>
+                                <div>
+                                    <ANorm href="/auth/logout">Log out</ANorm>
+                                </div>
+                            }
+                                .into_any()
+                        }
+                        Ok(None) | Err(_) => {
+                            // Errors getting the session are shown on pages that need it.
+                            view! {
+                                <div>
+                                    <ANorm href="/auth">Login/register</ANorm>
+                                </div>
+                            }
+                                .into_any()
+                        }
+                    }
+                })}
+            </Transition>
+        </nav>
+    }
+}
+
 /// Renders the home page of your application.
 #[component]
 fn HomePage() -> impl IntoView {
@@ -61,7 +112,7 @@ fn HomePage() -> impl IntoView {
 
 /// 404 - Not Found
 #[component]
-fn NotFound() -> impl IntoView {
+pub fn NotFound() -> impl IntoView {
     // set an HTTP status code 404
     // this is feature gated because it can only be done during
     // initial server-side rendering
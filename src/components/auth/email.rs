@@ -1,23 +1,29 @@
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
 use crate::components::ui::*;
 
 use codee::string::FromToStringCodec;
 use leptos::prelude::*;
 use leptos_router::components::*;
+use leptos_router::hooks::use_query_map;
 use leptos_router::{MatchNestedRoutes, path};
 use leptos_use::use_cookie;
+use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "ssr")]
-mod ssr {
+pub(super) mod ssr {
     pub use crate::ssr::app_state::*;
     pub use crate::ssr::cookie::*;
     pub use crate::ssr::key;
+    pub use crate::ssr::login::log_in_or_ask_second_factor;
     pub use crate::ssr::mail;
+    pub use crate::ssr::rate_limit::*;
+    pub use crate::ssr::request::client_ip;
 
     pub use actix_web::HttpRequest;
     pub use actix_web::cookie;
     pub use actix_web::cookie::Cookie;
     pub use fred::prelude::{HashesInterface, KeysInterface, TransactionInterface};
-    pub use leptos_actix::extract;
+    pub use leptos_actix::{ResponseOptions, extract};
     pub use lettre::AsyncTransport;
     pub use rand::{
         distributions::{Alphanumeric, DistString},
@@ -28,11 +34,163 @@ mod ssr {
 
     pub const CHALLENGE_REGCODE_LEN: usize = 16;
     pub const REGISTRATION_CODE_EXPIRATION_MIN: i64 = 60 * 2;
+    /// Minimum time between sending the same login code again.
+    pub const RESEND_COOLDOWN_SEC: i64 = 60;
+    /// Length of the token in login links. Unlike login codes, these are never typed in, and
+    /// are long enough that they can't be guessed.
+    pub const LINK_TOKEN_LEN: usize = 32;
+
+    use super::{ChallengeAnswer, LOGIN_CODE_EXPIRATION_MIN};
+    use leptos::prelude::ServerFnError;
+
+    /// Use up one of a login challenge's attempts before its answer is checked, so that guesses
+    /// made at the same time can't all be checked against the same count. Returns how many
+    /// attempts are left after this one, or None if there were none left, in which case the
+    /// challenge is invalidated.
+    pub async fn claim_login_attempt(
+        app_state: &AppState,
+        key: &str,
+    ) -> Result<Option<i64>, ServerFnError> {
+        let transaction = app_state.valkey_pool.multi();
+        let _: () = transaction.hincrby(key, "fails", 1).await?;
+        // In case the challenge expired in the meantime and the hincrby recreated it.
+        let _: () = transaction
+            .expire(
+                key,
+                LOGIN_CODE_EXPIRATION_MIN * 60,
+                Some(fred::types::ExpireOptions::NX),
+            )
+            .await?;
+        let (attempts, _): (i64, i64) = transaction.exec(true).await?;
+
+        if attempts > app_state.max_login_code_attempts {
+            app_state.valkey_pool.del::<(), _>(key).await?;
+            return Ok(None);
+        }
+        Ok(Some(app_state.max_login_code_attempts - attempts))
+    }
+
+    /// Reject a wrong answer to a login challenge, invalidating the challenge if that was its last
+    /// attempt.
+    pub async fn reject_login_attempt(
+        app_state: &AppState,
+        key: &str,
+        attempts_left: i64,
+    ) -> Result<ChallengeAnswer, ServerFnError> {
+        if attempts_left <= 0 {
+            app_state.valkey_pool.del::<(), _>(key).await?;
+        }
+        Ok(ChallengeAnswer::Rejected { attempts_left })
+    }
+
+    /// Log in or start registering the owner of an email address that was just proven, whether
+    /// by login code or by link. Also redirects to wherever the user should go next.
+    pub async fn finish_email_login(
+        app_state: &AppState,
+        request: &HttpRequest,
+        response_options: &ResponseOptions,
+        email: String,
+    ) -> Result<ChallengeAnswer, ServerFnError> {
+        match sqlx::query_as::<_, (Uuid,)>(
+            r#"
+            select id
+            from account
+            where
+              email = $1
+              or secondary_email @> array[$1]::email[]
+            limit 1
+            "#,
+        )
+        .bind(&email)
+        .fetch_optional(&app_state.db_pool)
+        .await
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Couldn't get account from DB: {err}"
+            )))
+        })? {
+            Some((account_id,)) => {
+                log_in_or_ask_second_factor(app_state, request, response_options, account_id)
+                    .await?;
+                Ok(ChallengeAnswer::Accepted)
+            }
+            None => {
+                let registration_code = {
+                    let mut registration_code = String::new();
+                    for i in 0..=10 {
+                        if i >= 10 {
+                            // This may happen in a very severe DDoS if there are no rate limiting
+                            // countermeasures at all. Otherwise, it should never happen.
+                            return Err(ServerFnError::new(
+                                "Couldn't generate a unique registration code. There may be a serious problem with entropy sources or keyspace saturation.",
+                            ));
+                        }
+                        registration_code =
+                            Alphanumeric.sample_string(&mut thread_rng(), CHALLENGE_REGCODE_LEN);
+                        if app_state
+                            .valkey_pool
+                            .set(
+                                key::new_registration(&registration_code),
+                                &email,
+                                Some(fred::types::Expiration::EX(
+                                    REGISTRATION_CODE_EXPIRATION_MIN * 60,
+                                )),
+                                Some(fred::prelude::SetOptions::NX),
+                                false,
+                            )
+                            .await?
+                        {
+                            break;
+                        }
+                    }
+                    registration_code
+                };
+
+                // Registration code is an HTTP only cookie, with similar security to a session token.
+                let registration_code_cookie = Cookie::build("regcode", registration_code)
+                    .max_age(cookie::time::Duration::minutes(
+                        REGISTRATION_CODE_EXPIRATION_MIN,
+                    ))
+                    .same_site(cookie::SameSite::Lax)
+                    .path("/")
+                    .http_only(true)
+                    // .secure(true) // No dev https setup.
+                    .finish();
+
+                // Can't use the same login email cookie because it has a different max_age.
+                let registration_email_cookie = Cookie::build("regmail", email)
+                    .max_age(cookie::time::Duration::minutes(
+                        REGISTRATION_CODE_EXPIRATION_MIN,
+                    ))
+                    .same_site(cookie::SameSite::Lax)
+                    .path("/")
+                    .finish();
+
+                set_cookie(response_options, &registration_code_cookie)?;
+                set_cookie(response_options, &registration_email_cookie)?;
+
+                leptos_actix::redirect("/auth/register");
+                Ok(ChallengeAnswer::Accepted)
+            }
+        }
+    }
 }
 
 const LOGIN_CODE_EXPIRATION_MIN: i64 = 20;
 const RESPONSE_LEN: usize = 8;
 
+/// Outcome of answering a login challenge.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ChallengeAnswer {
+    Accepted,
+    Rejected {
+        /// Wrong answers left before the code is invalidated.
+        attempts_left: i64,
+    },
+    /// The code expired, was already used, or was invalidated after too many wrong answers.
+    Expired,
+}
+
 /// Route definitions for email auth stages.
 #[component(transparent)]
 pub fn Routes() -> impl MatchNestedRoutes + Clone {
@@ -40,6 +198,7 @@ pub fn Routes() -> impl MatchNestedRoutes + Clone {
         <ParentRoute path=path!("email") view=EmailContainer>
             <Route path=path!("") view=Start />
             <Route path=path!("challenge") view=Challenge />
+            <Route path=path!("verify") view=Verify />
         </ParentRoute>
     }
     .into_inner()
@@ -49,7 +208,7 @@ pub fn Routes() -> impl MatchNestedRoutes + Clone {
 /// sent via email.
 ///
 /// See https://en.wikipedia.org/wiki/Challenge%E2%80%93response_authentication
-#[server]
+#[server(client = CsrfClient)]
 async fn get_email_login_challenge(email: String) -> Result<(), ServerFnError> {
     use self::ssr::*;
 
@@ -61,9 +220,19 @@ async fn get_email_login_challenge(email: String) -> Result<(), ServerFnError> {
     };
 
     let app_state = use_app_state()?;
+    let request: HttpRequest = extract().await?;
+    LOGIN_CHALLENGE_PER_IP
+        .check(&app_state.valkey_pool, &client_ip(&request))
+        .await?;
+    LOGIN_CHALLENGE_PER_EMAIL
+        .check(&app_state.valkey_pool, &email.to_lowercase())
+        .await?;
+
     let response = Alphanumeric.sample_string(&mut thread_rng(), RESPONSE_LEN);
+    let token = Alphanumeric.sample_string(&mut thread_rng(), LINK_TOKEN_LEN);
+    let link = format!("{}/auth/email/verify?token={token}", app_state.site_url);
 
-    let message = mail::login_code(address, &response, LOGIN_CODE_EXPIRATION_MIN)
+    let message = mail::login_code(address, &response, &link, LOGIN_CODE_EXPIRATION_MIN)
         .or_else(|err| Err(ServerFnError::new(format!("Couldn't send mail: {err}"))))?;
     app_state
         .mailer
@@ -93,11 +262,38 @@ async fn get_email_login_challenge(email: String) -> Result<(), ServerFnError> {
 
         let tx = app_state.valkey_pool.multi();
         let _: () = tx
-            .hset(&key, [("email", &email), ("response", &response)])
+            .hset(
+                &key,
+                [
+                    ("email", &email),
+                    ("response", &response),
+                    ("token", &token),
+                ],
+            )
             .await?;
         let _: () = tx
             .expire(&key, LOGIN_CODE_EXPIRATION_MIN * 60, None)
             .await?;
+        // Resending the code has to wait until this expires.
+        let _: () = tx
+            .set(
+                key::email_auth_resend(&challenge),
+                1,
+                Some(fred::types::Expiration::EX(RESEND_COOLDOWN_SEC)),
+                None,
+                false,
+            )
+            .await?;
+        // The link only knows the token, so it needs a way back to the challenge.
+        let _: () = tx
+            .set(
+                key::email_login_link(&token),
+                &challenge,
+                Some(fred::types::Expiration::EX(LOGIN_CODE_EXPIRATION_MIN * 60)),
+                None,
+                false,
+            )
+            .await?;
         let _: () = tx.exec(false).await?;
         challenge
     };
@@ -124,6 +320,115 @@ async fn get_email_login_challenge(email: String) -> Result<(), ServerFnError> {
     Ok(())
 }
 
+/// Send the code for the current login challenge again, e.g. if the first email got lost. The
+/// challenge and its expiration stay the same.
+#[server(client = CsrfClient)]
+async fn resend_login_code() -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let expired = || ServerFnError::new("Your login code expired. Request a new one.");
+
+    let request: HttpRequest = extract().await?;
+    let email = get_cookie(&request, "lgmail");
+    let challenge = get_cookie(&request, "lgchal");
+
+    if email.is_empty()
+        || challenge.len() != CHALLENGE_REGCODE_LEN
+        || !challenge.chars().all(char::is_alphanumeric)
+    {
+        return Err(expired());
+    }
+
+    let app_state = use_app_state()?;
+    let key = key::email_auth_code(&challenge);
+    let [correct_email, response, token] = app_state
+        .valkey_pool
+        .hmget::<[Option<String>; 3], _, _>(&key, ("email", "response", "token"))
+        .await?;
+    let (Some(correct_email), Some(response), Some(token)) = (correct_email, response, token)
+    else {
+        return Err(expired());
+    };
+    if email != correct_email {
+        return Err(expired());
+    }
+
+    // Claim the send before checking anything else, so that double submissions don't both send.
+    let resend_key = key::email_auth_resend(&challenge);
+    let claimed: Option<String> = app_state
+        .valkey_pool
+        .set(
+            &resend_key,
+            1,
+            Some(fred::types::Expiration::EX(RESEND_COOLDOWN_SEC)),
+            Some(fred::types::SetOptions::NX),
+            false,
+        )
+        .await?;
+    if claimed.is_none() {
+        let wait = app_state
+            .valkey_pool
+            .ttl::<i64, _>(&resend_key)
+            .await?
+            .clamp(1, RESEND_COOLDOWN_SEC);
+        return Err(ServerFnError::new(format!(
+            "Wait {wait} more seconds before sending the code again."
+        )));
+    }
+
+    LOGIN_CHALLENGE_PER_IP
+        .check(&app_state.valkey_pool, &client_ip(&request))
+        .await?;
+    LOGIN_CHALLENGE_PER_EMAIL
+        .check(&app_state.valkey_pool, &email.to_lowercase())
+        .await?;
+
+    let address = email
+        .parse::<lettre::address::Address>()
+        .or_else(|_| Err(ServerFnError::new("Bad email")))?;
+
+    let ttl = app_state.valkey_pool.ttl::<i64, _>(&key).await?;
+    if ttl <= 0 {
+        return Err(expired());
+    }
+
+    let link = format!("{}/auth/email/verify?token={token}", app_state.site_url);
+    let message = mail::login_code(address, &response, &link, (ttl + 59) / 60)
+        .or_else(|err| Err(ServerFnError::new(format!("Couldn't send mail: {err}"))))?;
+    app_state
+        .mailer
+        .send(message)
+        .await
+        .or_else(|err| Err(ServerFnError::new(format!("Couldn't send mail: {err}"))))?;
+
+    Ok(())
+}
+
+/// Abandon the current login challenge, e.g. to start over with a different email.
+#[server(client = CsrfClient)]
+async fn discard_login_challenge() -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let challenge = get_cookie(&request, "lgchal");
+
+    if challenge.len() == CHALLENGE_REGCODE_LEN && challenge.chars().all(char::is_alphanumeric) {
+        let app_state = use_app_state()?;
+        app_state
+            .valkey_pool
+            .del::<(), _>(key::email_auth_code(&challenge))
+            .await?;
+    }
+
+    let response_options = use_response_options()?;
+    remove_cookie(&response_options, "lgchal")?;
+    remove_cookie(&response_options, "lgmail")?;
+
+    leptos_actix::redirect("/auth/email");
+
+    Ok(())
+}
+
 /// Check the answer to a user's login challenge.
 ///
 /// If correct, also redirect to the home or registration page, depending on whether the user has
@@ -131,26 +436,28 @@ async fn get_email_login_challenge(email: String) -> Result<(), ServerFnError> {
 ///
 /// Note that, for security reasons, we can't tell the user which exactly of
 /// (email, challenge, response) was wrong.
-#[server]
-async fn answer_email_login_challenge(response: String) -> Result<bool, ServerFnError> {
+#[server(client = CsrfClient)]
+async fn answer_email_login_challenge(response: String) -> Result<ChallengeAnswer, ServerFnError> {
     use self::ssr::*;
 
     let request: HttpRequest = extract().await?;
     let email = get_cookie(&request, "lgmail");
     let challenge = get_cookie(&request, "lgchal");
 
-    if email.len() <= 0
+    if email.is_empty()
         || challenge.len() != CHALLENGE_REGCODE_LEN
-        || response.len() != RESPONSE_LEN
         || !challenge.chars().all(char::is_alphanumeric)
-        || !response.chars().all(char::is_alphanumeric)
     {
         leptos::logging::debug_warn!("Rejecting invalid login challenge inputs");
-        // Note that the actual form should never send these inputs.
-        return Ok(false);
+        // The cookies expire along with the challenge.
+        return Ok(ChallengeAnswer::Expired);
     }
 
     let app_state = use_app_state()?;
+    LOGIN_ANSWER_PER_IP
+        .check(&app_state.valkey_pool, &client_ip(&request))
+        .await?;
+
     let key = key::email_auth_code(&challenge);
     let correct_data: HashMap<String, String> = match app_state
         .valkey_pool
@@ -158,29 +465,39 @@ async fn answer_email_login_challenge(response: String) -> Result<bool, ServerFn
         .await?
     {
         Some(value) => value,
-        None => return Ok(false), // No matching challenge = wrong login.
+        None => return Ok(ChallengeAnswer::Expired), // No matching challenge = wrong login.
     };
 
     let correct_email = match correct_data.get("email") {
         Some(value) => value,
-        None => return Ok(false), // No email = wrong login.
+        None => return Ok(ChallengeAnswer::Expired), // No email = wrong login.
     };
+    // Limit by the challenge's own email rather than the cookie, which the client can change.
+    LOGIN_ANSWER_PER_EMAIL
+        .check(&app_state.valkey_pool, &correct_email.to_lowercase())
+        .await?;
     let correct_response = match correct_data.get("response") {
         Some(value) => value,
-        None => return Ok(false), // No response = wrong login.
+        None => return Ok(ChallengeAnswer::Expired), // No response = wrong login.
+    };
+    let Some(attempts_left) = claim_login_attempt(&app_state, &key).await? else {
+        return Ok(ChallengeAnswer::Expired);
     };
     if email != *correct_email || response != *correct_response {
-        return Ok(false); // Wrong email or response = wrong login.
+        // Wrong email or response = wrong login.
+        return reject_login_attempt(&app_state, &key, attempts_left).await;
     }
 
-    {
-        let valkey_pool = app_state.valkey_pool.clone();
-        // Response accepted; clean it up as it's a one-time code.
-        tokio::spawn(async move {
-            if let Err(err) = valkey_pool.del::<(), _>(&key).await {
-                leptos::logging::warn!("Error deleting key {key} ignored: {err}");
-            }
-        });
+    // Response accepted; clean it up along with the link, as both are one-time. If the challenge
+    // is already gone, the link was used in the meantime.
+    let tx = app_state.valkey_pool.multi();
+    let _: () = tx.del(&key).await?;
+    if let Some(token) = correct_data.get("token") {
+        let _: () = tx.del(key::email_login_link(token)).await?;
+    }
+    let deleted: Vec<i64> = tx.exec(true).await?;
+    if deleted.first().copied().unwrap_or_default() <= 0 {
+        return Ok(ChallengeAnswer::Expired);
     }
 
     let response_options = use_response_options()?;
@@ -188,97 +505,50 @@ async fn answer_email_login_challenge(response: String) -> Result<bool, ServerFn
     remove_cookie(&response_options, "lgchal")?;
     remove_cookie(&response_options, "lgmail")?;
 
-    match sqlx::query_as::<_, (Uuid, bool, Option<String>, Option<String>)>(
-        r#"
-        select
-          account.id,
-          ask_for_profile_on_login,
-          profile.username,
-          profile.display_name
-        from
-          account
-          left join profile on account.default_profile = profile.id
-        where
-          email = $1
-          or $1 = any(secondary_email)
-        limit 1
-        "#,
-    )
-    .bind(&email)
-    .fetch_optional(&app_state.db_pool)
-    .await
-    .or_else(|err| {
-        Err(ServerFnError::new(format!(
-            "Couldn't get account from DB: {err}"
-        )))
-    })? {
-        Some((account_id, _ask_for_profile_on_login, username, display_name)) => {
-            app_state
-                .create_session(&response_options, account_id, username, display_name)
-                .await?;
-            // TODO - Redirect to profile picker if applicable.
-            leptos_actix::redirect("/");
-            Ok(true)
-        }
-        None => {
-            let registration_code = {
-                let mut registration_code = String::new();
-                for i in 0..=10 {
-                    if i >= 10 {
-                        // This may happen in a very severe DDoS if there are no rate limiting
-                        // countermeasures at all. Otherwise, it should never happen.
-                        return Err(ServerFnError::new(
-                            "Couldn't generate a unique registration code. There may be a serious problem with entropy sources or keyspace saturation.",
-                        ));
-                    }
-                    registration_code =
-                        Alphanumeric.sample_string(&mut thread_rng(), CHALLENGE_REGCODE_LEN);
-                    if app_state
-                        .valkey_pool
-                        .set(
-                            key::new_registration(&registration_code),
-                            &email,
-                            Some(fred::types::Expiration::EX(
-                                REGISTRATION_CODE_EXPIRATION_MIN * 60,
-                            )),
-                            Some(fred::prelude::SetOptions::NX),
-                            false,
-                        )
-                        .await?
-                    {
-                        break;
-                    }
-                }
-                registration_code
-            };
-
-            // Registration code is an HTTP only cookie, with similar security to a session token.
-            let registration_code_cookie = Cookie::build("regcode", registration_code)
-                .max_age(cookie::time::Duration::minutes(
-                    REGISTRATION_CODE_EXPIRATION_MIN,
-                ))
-                .same_site(cookie::SameSite::Lax)
-                .path("/")
-                .http_only(true)
-                // .secure(true) // No dev https setup.
-                .finish();
-
-            // Can't use the same login email cookie because it has a different max_age.
-            let registration_email_cookie = Cookie::build("regmail", email)
-                .max_age(cookie::time::Duration::minutes(
-                    REGISTRATION_CODE_EXPIRATION_MIN,
-                ))
-                .same_site(cookie::SameSite::Lax)
-                .path("/")
-                .finish();
-
-            set_cookie(&response_options, &registration_code_cookie)?;
-            set_cookie(&response_options, &registration_email_cookie)?;
-
-            leptos_actix::redirect("/auth/register");
-            Ok(true)
-        }
+    finish_email_login(&app_state, &request, &response_options, email).await
+}
+
+/// Complete a login challenge through the link sent with the login code, instead of typing in the
+/// code. This works from any browser, since the token identifies the challenge on its own.
+#[server(client = CsrfClient)]
+async fn verify_email_login_link(token: String) -> Result<ChallengeAnswer, ServerFnError> {
+    use self::ssr::*;
+
+    if token.len() != LINK_TOKEN_LEN || !token.chars().all(char::is_alphanumeric) {
+        return Ok(ChallengeAnswer::Expired);
     }
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    LOGIN_ANSWER_PER_IP
+        .check(&app_state.valkey_pool, &client_ip(&request))
+        .await?;
+
+    let Some(challenge) = app_state
+        .valkey_pool
+        .getdel::<Option<String>, _>(key::email_login_link(&token))
+        .await?
+    else {
+        return Ok(ChallengeAnswer::Expired);
+    };
+
+    // Consume the challenge too, so the code can't be used after the link.
+    let key = key::email_auth_code(&challenge);
+    let tx = app_state.valkey_pool.multi();
+    let _: () = tx.hget(&key, "email").await?;
+    let _: () = tx.del(&key).await?;
+    let (email, deleted): (Option<String>, i64) = tx.exec(true).await?;
+    let Some(email) = email.filter(|_| deleted > 0) else {
+        return Ok(ChallengeAnswer::Expired);
+    };
+
+    let response_options = use_response_options()?;
+
+    // In case this is the browser that requested the code.
+    remove_cookie(&response_options, "lgchal")?;
+    remove_cookie(&response_options, "lgmail")?;
+
+    finish_email_login(&app_state, &request, &response_options, email).await
 }
 
 /// Common email challenge info.
@@ -297,7 +567,7 @@ pub fn EmailContainer() -> impl IntoView {
 pub fn Start() -> impl IntoView {
     let get_email_login_challenge = ServerAction::<GetEmailLoginChallenge>::new();
     view! {
-        <ActionForm action=get_email_login_challenge>
+        <CsrfActionForm action=get_email_login_challenge>
             <div class="flex gap-2">
                 <label for="email">Email:</label>
                 <input
@@ -312,8 +582,9 @@ pub fn Start() -> impl IntoView {
                     value="Email me"
                     class="px-2 h-full bg-green-200 hover:bg-green-300"
                 />
+                <ShowActionStatus action=get_email_login_challenge />
             </div>
-        </ActionForm>
+        </CsrfActionForm>
     }
 }
 
@@ -322,37 +593,50 @@ pub fn Challenge() -> impl IntoView {
     let (email, _) = use_cookie::<String, FromToStringCodec>("lgmail");
 
     let answer_email_login_challenge = ServerAction::<AnswerEmailLoginChallenge>::new();
+    let resend_login_code = ServerAction::<ResendLoginCode>::new();
+    let discard_login_challenge = ServerAction::<DiscardLoginChallenge>::new();
 
     view! {
         <Show when=move || email.read().is_none()>
             <Redirect path=".." />
         </Show>
 
-        <ActionForm action=answer_email_login_challenge>
-            <div class="flex gap-2">
-                <label for="email">Email:</label>
+        <div class="flex gap-2">
+            <label for="email">Email:</label>
+            <input
+                type="email"
+                name="email"
+                placeholder="email"
+                class="px-1 h-full bg-gray-200 border border-gray-500 invalid:border-red-500"
+                required
+                // We have the email as a cookie already; we don't have to resend it.
+                disabled
+                value=email
+            />
+            <CsrfActionForm action=resend_login_code>
                 <input
-                    type="email"
-                    name="email"
-                    placeholder="email"
-                    class="px-1 h-full bg-gray-200 border border-gray-500 invalid:border-red-500"
-                    required
-                    // We have the email as a cookie already; we don't have to resend it.
-                    disabled
-                    value=email
+                    type="submit"
+                    value="Resend code"
+                    class="px-2 h-full bg-green-200 hover:bg-green-300"
                 />
+            </CsrfActionForm>
+            <CsrfActionForm action=discard_login_challenge>
                 <input
                     type="submit"
-                    value="Email me"
-                    class="px-2 h-full bg-green-200 hover:bg-green-300"
-                    disabled
+                    value="Use a different email"
+                    class="px-2 h-full bg-slate-200 hover:bg-slate-300"
                 />
-            </div>
+            </CsrfActionForm>
+            <ShowActionStatus action=resend_login_code success="Login code sent again." />
+            <ShowActionStatus action=discard_login_challenge />
+        </div>
 
+        <CsrfActionForm action=answer_email_login_challenge>
             <p>
                 "An email has been sent to " {move || email()}
-                " with a login code; please enter it here within " {LOGIN_CODE_EXPIRATION_MIN}
-                " minutes".
+                " with a login code; please enter it here, or open the link in the email, within "
+                {LOGIN_CODE_EXPIRATION_MIN}
+                " minutes. After too many wrong attempts, the code stops working and you'll need a new one."
             </p>
 
             <div class="flex gap-2">
@@ -383,24 +667,87 @@ pub fn Challenge() -> impl IntoView {
                     fallback=move || view! { <Spinner /> }
                 >
                     {move || {
-                        if let Some(value) = answer_email_login_challenge.value().get() {
-                            if let Err(err) = value {
-                                view! { <ShowServerFnError error=err /> }.into_any()
-                            } else if let Ok(true) = value {
+                        match answer_email_login_challenge.value().get() {
+                            Some(Err(err)) => view! { <ShowServerFnError error=err /> }.into_any(),
+                            Some(Ok(ChallengeAnswer::Accepted)) => {
                                 view! {
                                     "Login code accepted. You will be automatically redirected shortly."
                                 }
                                     .into_any()
-                            } else {
-                                view! { "Login code rejected. Try again." }.into_any()
                             }
-                        } else {
-                            view! { "" }.into_any()
+                            Some(
+                                Ok(ChallengeAnswer::Rejected { attempts_left }),
+                            ) if attempts_left > 0 => {
+                                view! {
+                                    "Login code rejected. Try again. "
+                                    {attempts_left}
+                                    {if attempts_left == 1 { " attempt" } else { " attempts" }}
+                                    " left."
+                                }
+                                    .into_any()
+                            }
+                            Some(Ok(_)) => {
+                                view! {
+                                    "This login code expired or was entered wrong too many times. "
+                                    <ANorm href="/auth/email">"Request a new one"</ANorm>
+                                    "."
+                                }
+                                    .into_any()
+                            }
+                            None => view! { "" }.into_any(),
                         }
                     }}
                 </Show>
             </div>
-        </ActionForm>
+        </CsrfActionForm>
     }
     .into_any()
 }
+
+/// Landing page for login links. Logging in takes a click rather than happening on page load,
+/// since some email providers open links in emails to scan them.
+#[component]
+pub fn Verify() -> impl IntoView {
+    let query = use_query_map();
+    let token = move || query.read().get("token").unwrap_or_default();
+
+    let verify_email_login_link = ServerAction::<VerifyEmailLoginLink>::new();
+
+    view! {
+        <CsrfActionForm action=verify_email_login_link>
+            <input type="hidden" name="token" value=token />
+            <div class="flex gap-2">
+                <input
+                    type="submit"
+                    value="Continue logging in"
+                    class="px-2 h-full bg-green-200 hover:bg-green-300"
+                />
+                <Show
+                    when=move || { !verify_email_login_link.pending().get() }
+                    fallback=move || view! { <Spinner /> }
+                >
+                    {move || {
+                        match verify_email_login_link.value().get() {
+                            Some(Err(err)) => view! { <ShowServerFnError error=err /> }.into_any(),
+                            Some(Ok(ChallengeAnswer::Accepted)) => {
+                                view! {
+                                    "Login link accepted. You will be automatically redirected shortly."
+                                }
+                                    .into_any()
+                            }
+                            Some(Ok(_)) => {
+                                view! {
+                                    "This login link expired or was already used. "
+                                    <ANorm href="/auth/email">"Request a new one"</ANorm>
+                                    "."
+                                }
+                                    .into_any()
+                            }
+                            None => view! { "" }.into_any(),
+                        }
+                    }}
+                </Show>
+            </div>
+        </CsrfActionForm>
+    }
+}
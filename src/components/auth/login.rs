@@ -1,4 +1,7 @@
 /// Login/new registration views.
+use super::oidc::{LoginProviderEntry, get_login_providers};
+use crate::components::ui::*;
+
 use leptos::prelude::*;
 use leptos_router::components::A;
 
@@ -23,6 +26,8 @@ fn LoginMethodEntry(
 /// Main login page.
 #[component]
 pub fn LoginMethods() -> impl IntoView {
+    let providers = OnceResource::new(get_login_providers());
+
     view! {
         <div class="flex flex-col gap-4">
             <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
@@ -36,15 +41,23 @@ pub fn LoginMethods() -> impl IntoView {
             <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
                 <legend class="m-2 text-2xl font-bold">Login only</legend>
                 <div class="flex flex-row gap-2">
-                    <LoginMethodEntry endpoint="" text_label="?">
-                        "?"
-                    </LoginMethodEntry>
-                    <LoginMethodEntry endpoint="" text_label="?">
-                        "?"
-                    </LoginMethodEntry>
-                    <LoginMethodEntry endpoint="" text_label="?">
-                        "?"
+                    <LoginMethodEntry endpoint="passkey" text_label="Passkey">
+                        "🔑"
                     </LoginMethodEntry>
+                    <Transition>
+                        {move || Suspend::new(async move {
+                            match providers.await {
+                                Ok(providers) => {
+                                    providers
+                                        .into_iter()
+                                        .map(|provider| view! { <LoginProviderEntry provider /> })
+                                        .collect_view()
+                                        .into_any()
+                                }
+                                Err(err) => view! { <ShowServerFnError error=err /> }.into_any(),
+                            }
+                        })}
+                    </Transition>
                 </div>
             </fieldset>
         </div>
@@ -0,0 +1,93 @@
+/// Logout views.
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos_router::components::*;
+use leptos_router::{MatchNestedRoutes, path};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::cookie::*;
+
+    pub use actix_web::HttpRequest;
+    pub use leptos_actix::extract;
+}
+
+/// Route definitions for logging out.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("logout") view=LogoutOptions /> }.into_inner()
+}
+
+/// End the current session.
+#[server(client = CsrfClient)]
+async fn logout() -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let response_options = use_response_options()?;
+
+    app_state.end_session(request, &response_options).await?;
+
+    leptos_actix::redirect("/");
+
+    Ok(())
+}
+
+/// End every session of the logged in account, including the current one.
+#[server(client = CsrfClient)]
+async fn logout_everywhere() -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let response_options = use_response_options()?;
+
+    let session = app_state.require_session(request.clone()).await?;
+
+    app_state.end_all_sessions(session.account_id).await?;
+    app_state.end_session(request, &response_options).await?;
+
+    leptos_actix::redirect("/");
+
+    Ok(())
+}
+
+/// Main logout page.
+#[component]
+pub fn LogoutOptions() -> impl IntoView {
+    let logout = ServerAction::<Logout>::new();
+    let logout_everywhere = ServerAction::<LogoutEverywhere>::new();
+
+    view! {
+        <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
+            <legend class="m-2 text-2xl font-bold">Log out</legend>
+            <CsrfActionForm action=logout>
+                <div class="py-2">
+                    <input
+                        type="submit"
+                        value="Log out"
+                        class="py-0.5 px-2 font-bold bg-slate-200 hover:bg-slate-400"
+                    />
+                </div>
+            </CsrfActionForm>
+            <ShowActionStatus action=logout />
+            <CsrfActionForm action=logout_everywhere>
+                <p>
+                    "If you forgot to log out on a shared device, or think someone else may be using your account, you can log out of every device at once."
+                </p>
+                <div class="py-2">
+                    <input
+                        type="submit"
+                        value="Log out everywhere"
+                        class="py-0.5 px-2 font-bold bg-red-200 hover:bg-red-400"
+                    />
+                </div>
+            </CsrfActionForm>
+            <ShowActionStatus action=logout_everywhere />
+        </fieldset>
+    }
+}
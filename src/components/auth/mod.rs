@@ -5,7 +5,13 @@ use leptos_router::*;
 
 mod email;
 mod login;
+mod logout;
+pub mod oidc;
+mod passkey;
+mod profile;
 mod register;
+mod totp;
+mod undo_email_change;
 
 /// Visual wrapper around all auth views, but there isn't much to show.
 #[component]
@@ -23,7 +29,13 @@ pub fn AuthRoutes() -> impl MatchNestedRoutes + Clone {
         <ParentRoute path=path!("auth") view=AuthWrapper>
             <Route path=path!("") view=login::LoginMethods />
             <email::Routes />
+            <logout::Routes />
+            <oidc::Routes />
+            <passkey::Routes />
+            <profile::Routes />
             <register::Routes />
+            <totp::Routes />
+            <undo_email_change::Routes />
             <Route path=path!("register") view=register::Register />
         </ParentRoute>
     }
@@ -0,0 +1,221 @@
+/// Logging in with OpenID Connect providers. Identities are linked to accounts from the
+/// settings, so this can't register.
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos_router::SsrMode;
+use leptos_router::components::*;
+use leptos_router::hooks::use_query_map;
+use leptos_router::{MatchNestedRoutes, path};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::cookie::use_response_options;
+    pub use crate::ssr::login::log_in_or_ask_second_factor;
+    pub use crate::ssr::oidc::*;
+
+    pub use actix_web::HttpRequest;
+    pub use leptos_actix::extract;
+    pub use uuid::Uuid;
+
+    use leptos::prelude::ServerFnError;
+
+    /// Link an identity the provider vouched for to the logged in account.
+    pub async fn link_identity(
+        app_state: &AppState,
+        request: HttpRequest,
+        provider: &str,
+        account_id: Uuid,
+        identity: VerifiedIdentity,
+    ) -> Result<(), ServerFnError> {
+        let session = app_state.require_session(request).await?;
+        if session.account_id != account_id {
+            return Err(ServerFnError::new(
+                "You logged in as someone else while linking. Try again.",
+            ));
+        }
+
+        let inserted = sqlx::query(
+            r#"
+            insert into linked_identity (account_id, provider, issuer, subject, email)
+            values ($1, $2, $3, $4, $5)
+            on conflict (issuer, subject) do nothing
+            "#,
+        )
+        .bind(account_id)
+        .bind(provider)
+        .bind(&identity.issuer)
+        .bind(&identity.subject)
+        .bind(&identity.email)
+        .execute(&app_state.db_pool)
+        .await
+        .or_else(|err| Err(ServerFnError::new(format!("Failed to link login: {err}"))))?
+        .rows_affected();
+        if inserted == 0 {
+            return Err(ServerFnError::new(
+                "That login is already linked, to this or another account. Unlink it there first.",
+            ));
+        }
+
+        leptos_actix::redirect("/settings/logins");
+        Ok(())
+    }
+}
+
+/// Route definitions for provider logins.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    // Rendered all at once, so finishing the login can redirect with the response itself.
+    view! { <Route path=path!("oidc/callback") view=OidcCallback ssr=SsrMode::Async /> }
+        .into_inner()
+}
+
+/// A provider users can log in with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LoginProvider {
+    pub id: String,
+    pub name: String,
+}
+
+/// Get every configured provider.
+#[server(client = CsrfClient)]
+pub async fn get_login_providers() -> Result<Vec<LoginProvider>, ServerFnError> {
+    use self::ssr::*;
+
+    let app_state = use_app_state()?;
+    Ok(app_state
+        .oidc
+        .providers
+        .iter()
+        .map(|provider| LoginProvider {
+            id: provider.id.clone(),
+            name: provider.name.clone(),
+        })
+        .collect())
+}
+
+/// Send the user to a provider to log in.
+#[server(client = CsrfClient)]
+pub async fn start_oidc_login(provider: String) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let app_state = use_app_state()?;
+    let response_options = use_response_options()?;
+    start_oidc_flow(&app_state, &response_options, &provider, None).await
+}
+
+/// Finish logging in with, or linking, an identity when the provider sends the user back.
+#[server(client = CsrfClient)]
+async fn finish_oidc(
+    state: String,
+    code: String,
+    error: Option<String>,
+) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    if let Some(error) = error {
+        return Err(ServerFnError::new(format!(
+            "The login provider didn't log you in: {error}"
+        )));
+    }
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let response_options = use_response_options()?;
+    let (flow, identity) =
+        finish_oidc_flow(&app_state, &request, &response_options, &state, &code).await?;
+
+    if let Some(account_id) = flow.link_to {
+        return link_identity(&app_state, request, &flow.provider, account_id, identity).await;
+    }
+
+    let (account_id,) = sqlx::query_as::<_, (Uuid,)>(
+        r#"
+        update linked_identity
+        set last_used_at = now()
+        where issuer = $1
+          and subject = $2
+        returning account_id
+        "#,
+    )
+    .bind(&identity.issuer)
+    .bind(&identity.subject)
+    .fetch_optional(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get linked login from DB: {err}"
+        )))
+    })?
+    .ok_or_else(|| {
+        ServerFnError::new(
+            "That login isn't linked to any account. Log in another way, then link it in your settings.",
+        )
+    })?;
+
+    log_in_or_ask_second_factor(&app_state, &request, &response_options, account_id).await
+}
+
+/// Button to log in with a provider.
+#[component]
+pub fn LoginProviderEntry(provider: LoginProvider) -> impl IntoView {
+    let start_oidc_login = ServerAction::<StartOidcLogin>::new();
+
+    view! {
+        <CsrfActionForm action=start_oidc_login>
+            <input type="hidden" name="provider" value=provider.id />
+            <div class="flex flex-col items-center">
+                <button
+                    type="submit"
+                    class="p-4 text-4xl font-bold bg-transparent border-4 hover:bg-gray-300"
+                >
+                    "🌐"
+                </button>
+                <p>{provider.name}</p>
+                <ShowActionStatus action=start_oidc_login />
+            </div>
+        </CsrfActionForm>
+    }
+}
+
+/// Page providers send users back to. It finishes the login or link as it loads, and the
+/// response redirects onward unless something went wrong.
+#[component]
+pub fn OidcCallback() -> impl IntoView {
+    let query = use_query_map();
+    let result = Resource::new(
+        move || {
+            let query = query.read();
+            (
+                query.get("state").unwrap_or_default(),
+                query.get("code").unwrap_or_default(),
+                query.get("error"),
+            )
+        },
+        |(state, code, error)| finish_oidc(state, code, error),
+    );
+
+    view! {
+        <Suspense fallback=move || {
+            view! { <Spinner /> }
+        }>
+            {move || Suspend::new(async move {
+                match result.await {
+                    Ok(()) => view! { <p>"Done. Redirecting..."</p> }.into_any(),
+                    Err(err) => {
+                        view! {
+                            <ShowServerFnError error=err />
+                            <p>
+                                <ANorm href="/auth">"Back to login"</ANorm>
+                            </p>
+                        }
+                            .into_any()
+                    }
+                }
+            })}
+        </Suspense>
+    }
+}
@@ -0,0 +1,220 @@
+/// Logging in with a passkey. Passkeys are added from the settings, so this can't register.
+use crate::components::csrf::CsrfClient;
+use crate::components::passkey::get_credential;
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos::server_fn::codec::Json;
+use leptos_router::components::*;
+use leptos_router::{MatchNestedRoutes, path};
+use serde::{Deserialize, Serialize};
+use webauthn_rs_proto::{PublicKeyCredential, RequestChallengeResponse};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::cookie::use_response_options;
+    pub use crate::ssr::key;
+    pub use crate::ssr::login::log_in_account;
+    pub use crate::ssr::rate_limit::*;
+    pub use crate::ssr::request::client_ip;
+
+    pub use actix_web::HttpRequest;
+    pub use fred::prelude::KeysInterface;
+    pub use leptos_actix::extract;
+    pub use rand::{
+        distributions::{Alphanumeric, DistString},
+        thread_rng,
+    };
+    pub use uuid::Uuid;
+    pub use webauthn_rs::prelude::{DiscoverableAuthentication, Passkey};
+
+    /// How long the browser has to sign a login challenge.
+    pub const PASSKEY_LOGIN_TIMEOUT_SEC: i64 = 5 * 60;
+    pub const CHALLENGE_ID_LEN: usize = 16;
+}
+
+/// Route definitions for passkey login.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("passkey") view=PasskeyLogin /> }.into_inner()
+}
+
+/// A login challenge for any passkey, and the ID to answer it under.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PasskeyChallenge {
+    pub id: String,
+    pub options: RequestChallengeResponse,
+}
+
+/// Start logging in with a passkey. The browser picks which one, so no account is needed yet.
+#[server(client = CsrfClient)]
+async fn start_passkey_login() -> Result<PasskeyChallenge, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    PASSKEY_LOGIN_PER_IP
+        .check(&app_state.valkey_pool, &client_ip(&request))
+        .await?;
+
+    let (mut options, authentication) = app_state
+        .webauthn
+        .start_discoverable_authentication()
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Failed to start passkey login: {err}"
+            )))
+        })?;
+    // This is started by a button rather than from autofill.
+    options.mediation = None;
+
+    let authentication = serde_json::to_string(&authentication).or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to save passkey login: {err}"
+        )))
+    })?;
+    let id = Alphanumeric.sample_string(&mut thread_rng(), CHALLENGE_ID_LEN);
+    app_state
+        .valkey_pool
+        .set::<(), _, _>(
+            key::passkey_login(&id),
+            authentication,
+            Some(fred::types::Expiration::EX(PASSKEY_LOGIN_TIMEOUT_SEC)),
+            None,
+            false,
+        )
+        .await?;
+
+    Ok(PasskeyChallenge { id, options })
+}
+
+/// Finish logging in with the credential the browser signed the challenge with.
+#[server(input = Json, client = CsrfClient)]
+async fn finish_passkey_login(
+    id: String,
+    credential: PublicKeyCredential,
+) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let expired = || ServerFnError::new("Logging in took too long. Try again.");
+    let unknown = || {
+        ServerFnError::new(
+            "That passkey isn't added to any account. Log in another way, then add it in your settings.",
+        )
+    };
+
+    if id.len() != CHALLENGE_ID_LEN || !id.chars().all(char::is_alphanumeric) {
+        return Err(expired());
+    }
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+
+    // Each challenge can only be answered once.
+    let authentication = app_state
+        .valkey_pool
+        .getdel::<Option<String>, _>(key::passkey_login(&id))
+        .await?
+        .ok_or_else(expired)?;
+    let authentication: DiscoverableAuthentication = serde_json::from_str(&authentication)
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Failed to read passkey login: {err}"
+            )))
+        })?;
+
+    let (account_id, credential_id) = app_state
+        .webauthn
+        .identify_discoverable_authentication(&credential)
+        .or_else(|_| Err(unknown()))?;
+
+    let (passkey_id, passkey) = sqlx::query_as::<_, (Uuid, String)>(
+        r#"
+        select id, passkey::text
+        from webauthn_credential
+        where account_id = $1
+          and credential_id = $2
+        "#,
+    )
+    .bind(account_id)
+    .bind(credential_id)
+    .fetch_optional(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get passkey from DB: {err}"
+        )))
+    })?
+    .ok_or_else(unknown)?;
+    let mut passkey: Passkey = serde_json::from_str(&passkey)
+        .or_else(|err| Err(ServerFnError::new(format!("Failed to read passkey: {err}"))))?;
+
+    let result = app_state
+        .webauthn
+        .finish_discoverable_authentication(&credential, authentication, &[(&passkey).into()])
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "The passkey couldn't be verified: {err}"
+            )))
+        })?;
+
+    // The signature counter and backup state can change with every use.
+    let updated = passkey.update_credential(&result).unwrap_or(false);
+    let serialized = serde_json::to_string(&passkey)
+        .or_else(|err| Err(ServerFnError::new(format!("Failed to save passkey: {err}"))))?;
+    sqlx::query(
+        r#"
+        update webauthn_credential
+        set
+          passkey = case when $2 then $3::jsonb else passkey end,
+          last_used_at = now()
+        where id = $1
+        "#,
+    )
+    .bind(passkey_id)
+    .bind(updated)
+    .bind(serialized)
+    .execute(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to update passkey: {err}"
+        )))
+    })?;
+
+    let response_options = use_response_options()?;
+    log_in_account(&app_state, &request, &response_options, account_id).await
+}
+
+/// Run the whole passkey login ceremony: get a challenge, have the browser sign it, and send it
+/// back.
+async fn log_in_with_passkey() -> Result<(), ServerFnError> {
+    let challenge = start_passkey_login().await?;
+    let credential = get_credential(challenge.options).await?;
+    finish_passkey_login(challenge.id, credential).await
+}
+
+/// Page to log in with a passkey.
+#[component]
+pub fn PasskeyLogin() -> impl IntoView {
+    let log_in = Action::new_unsync(|_: &()| log_in_with_passkey());
+
+    view! {
+        <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
+            <legend class="m-2 text-2xl font-bold">"Passkey login"</legend>
+            <p>
+                "Log in with a passkey you've added to your account, using your device's screen lock or a security key. To add a passkey, log in another way first and go to your settings."
+            </p>
+            <button
+                class="py-0.5 px-2 my-2 font-bold bg-green-200 hover:bg-green-400"
+                on:click=move |_| {
+                    log_in.dispatch(());
+                }
+            >
+                "Log in with a passkey"
+            </button>
+            <ShowClientActionStatus action=log_in />
+        </fieldset>
+    }
+}
@@ -0,0 +1,226 @@
+/// Profile picker, used after login and to switch profiles without logging out.
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::session::RequireLogin;
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos_router::components::*;
+use leptos_router::{MatchNestedRoutes, path};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::app_state::*;
+
+    pub use actix_web::HttpRequest;
+    pub use leptos_actix::extract;
+}
+
+/// Route definitions for profile picking.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("profile") view=ProfilePicker /> }.into_inner()
+}
+
+/// A profile that the current account can act as.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProfileChoice {
+    pub username: String,
+    pub display_name: Option<String>,
+}
+
+/// Profiles owned by the current account.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProfileChoices {
+    /// Username of the profile the session is currently acting as. Empty in reader mode.
+    pub current: String,
+    pub profiles: Vec<ProfileChoice>,
+}
+
+/// Get every profile owned by the logged in account.
+#[server(client = CsrfClient)]
+async fn get_own_profiles() -> Result<ProfileChoices, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let profiles = sqlx::query_as::<_, (String, Option<String>)>(
+        r#"
+        select username, display_name
+        from profile
+        where account_id = $1
+        order by created_at
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get profiles from DB: {err}"
+        )))
+    })?
+    .into_iter()
+    .map(|(username, display_name)| ProfileChoice {
+        username,
+        display_name,
+    })
+    .collect();
+
+    Ok(ProfileChoices {
+        current: session.username,
+        profiles,
+    })
+}
+
+/// Switch the current session to the given profile. An empty username switches to reader mode.
+#[server(client = CsrfClient)]
+async fn pick_profile(username: String) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    if username.is_empty() {
+        app_state
+            .set_session_profile(&session.session_id, None, None)
+            .await?;
+    } else {
+        // Only allow profiles that are owned by the logged in account.
+        let display_name = match sqlx::query_as::<_, (Option<String>,)>(
+            r#"
+            select display_name
+            from profile
+            where account_id = $1
+              and username = $2
+            "#,
+        )
+        .bind(session.account_id)
+        .bind(&username)
+        .fetch_optional(&app_state.db_pool)
+        .await
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Couldn't get profile from DB: {err}"
+            )))
+        })? {
+            Some((display_name,)) => display_name,
+            None => return Err(ServerFnError::new("You don't have a profile by that name.")),
+        };
+
+        app_state
+            .set_session_profile(&session.session_id, Some(username), display_name)
+            .await?;
+    }
+
+    leptos_actix::redirect("/");
+
+    Ok(())
+}
+
+/// One entry in the profile picker.
+#[component]
+fn ProfileChoiceEntry(
+    action: ServerAction<PickProfile>,
+    username: String,
+    label: String,
+    current: bool,
+) -> impl IntoView {
+    view! {
+        <CsrfActionForm action=action>
+            <input type="hidden" name="username" value=username />
+            <button
+                type="submit"
+                class="py-0.5 px-2 w-full text-left bg-slate-200 hover:bg-slate-400"
+                class=("font-bold", current)
+            >
+                {label}
+                {current.then_some(" (current)")}
+            </button>
+        </CsrfActionForm>
+    }
+}
+
+/// Page for picking which profile (or reader mode) to act as.
+#[component]
+pub fn ProfilePicker() -> impl IntoView {
+    let choices = Resource::new(|| (), |_| get_own_profiles());
+    let pick_profile = ServerAction::<PickProfile>::new();
+
+    view! {
+        <RequireLogin>
+            <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
+                <legend class="m-2 text-2xl font-bold">Pick a profile</legend>
+                <Suspense fallback=move || {
+                    view! { <Spinner /> }
+                }>
+                    {move || Suspend::new(async move {
+                        match choices.await {
+                            Ok(choices) => {
+                                view! {
+                                    <div class="flex flex-col gap-2 max-w-md">
+                                        {choices
+                                            .profiles
+                                            .into_iter()
+                                            .map(|profile| {
+                                                let current = profile.username == choices.current;
+                                                let label = match profile.display_name {
+                                                    Some(display_name) if !display_name.is_empty() => {
+                                                        format!("{display_name} (@{})", profile.username)
+                                                    }
+                                                    _ => format!("@{}", profile.username),
+                                                };
+                                                view! {
+                                                    <ProfileChoiceEntry
+                                                        action=pick_profile
+                                                        username=profile.username
+                                                        label=label
+                                                        current=current
+                                                    />
+                                                }
+                                            })
+                                            .collect_view()}
+                                        <ProfileChoiceEntry
+                                            action=pick_profile
+                                            username=String::new()
+                                            label=String::from("Reader mode")
+                                            current=choices.current.is_empty()
+                                        />
+                                    </div>
+                                    <p>
+                                        "Reader mode lets you browse without a profile. You can't post or vote in reader mode."
+                                    </p>
+                                }
+                                    .into_any()
+                            }
+                            Err(err) => {
+                                view! {
+                                    <ShowServerFnError error=err />
+                                    <p>
+                                        <ANorm href="/auth">"Log in"</ANorm>
+                                    </p>
+                                }
+                                    .into_any()
+                            }
+                        }
+                    })}
+                </Suspense>
+                <Show
+                    when=move || { !pick_profile.pending().get() }
+                    fallback=move || view! { <Spinner /> }
+                >
+                    {move || {
+                        if let Some(Err(err)) = pick_profile.value().get() {
+                            view! { <ShowServerFnError error=err /> }.into_any()
+                        } else {
+                            view! { "" }.into_any()
+                        }
+                    }}
+                </Show>
+            </fieldset>
+        </RequireLogin>
+    }
+}
@@ -1,21 +1,173 @@
+use crate::components::account_error::*;
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
 use crate::components::ui::*;
+use crate::components::username::*;
 
 use codee::string::FromToStringCodec;
 use leptos::prelude::*;
 use leptos_router::components::*;
 use leptos_router::{MatchNestedRoutes, path};
-use leptos_use::use_cookie;
+use leptos_use::{signal_debounced, use_cookie};
 
 #[cfg(feature = "ssr")]
 mod ssr {
+    pub use crate::components::account_error::AccountError;
     pub use crate::ssr::app_state::*;
     pub use crate::ssr::cookie::*;
+    pub use crate::ssr::key;
+    pub use crate::ssr::username::check_username_allowed;
 
     pub use actix_web::HttpRequest;
+    pub use fred::prelude::KeysInterface;
     pub use leptos_actix::extract;
     pub use sqlx::Executor;
     pub use sqlx::Row;
     pub use uuid::Uuid;
+
+    /// Create an account for the email proven by the HTTP-only registration code cookie. The code
+    /// is consumed in the process, so it can only create one account.
+    pub async fn create_account(
+        create_profile: Option<String>,
+        display_name: Option<String>,
+        username: Option<String>,
+        bio: Option<String>,
+    ) -> Result<(), AccountError> {
+        let expired = || {
+            AccountError::Other(String::from(
+                "Your registration code expired or was already used. Verify your email again.",
+            ))
+        };
+
+        let request: HttpRequest = extract().await?;
+        let code = get_cookie(&request, "regcode");
+
+        if code.is_empty() || !code.chars().all(char::is_alphanumeric) {
+            return Err(expired());
+        }
+
+        let app_state = use_app_state()?;
+        let registration_key = key::new_registration(&code);
+
+        let create_profile = create_profile.is_some_and(|c| c == "true");
+        if create_profile {
+            // A new account can't be an admin yet, so there's no account to exempt.
+            check_username_allowed(
+                &app_state.db_pool,
+                username.as_deref().unwrap_or_default(),
+                None,
+            )
+            .await?;
+        }
+
+        // The regmail cookie is only for display, since anyone can edit it. This is the email that was
+        // actually proven.
+        let email = app_state
+            .valkey_pool
+            .get::<Option<String>, _>(&registration_key)
+            .await?
+            .ok_or_else(expired)?;
+
+        // Create a transaction for both creating the account and the profile.
+        let mut transaction = app_state.db_pool.begin().await.or_else(|err| {
+            Err(AccountError::from_db(
+                err,
+                "Failed to create transaction for account creation",
+            ))
+        })?;
+
+        let id: Uuid = transaction
+            .fetch_one(
+                sqlx::query(
+                    r#"
+                    insert into account (email)
+                    values ($1)
+                    returning id
+                    "#,
+                )
+                .bind(&email),
+            )
+            .await
+            .or_else(|err| Err(AccountError::from_db(err, "Failed to create account")))?
+            .get(0);
+
+        if create_profile {
+            let profile_id: Uuid = transaction
+                .fetch_one(
+                    sqlx::query(
+                        r#"
+                        insert into profile (username, account_id, display_name, bio)
+                        values ($1, $2, $3, $4)
+                        returning id
+                        "#,
+                    )
+                    .bind(&username)
+                    .bind(id)
+                    .bind(&display_name)
+                    .bind(&bio),
+                )
+                .await
+                .or_else(|err| Err(AccountError::from_db(err, "Failed to create profile")))?
+                .get(0);
+
+            if transaction
+                .execute(
+                    sqlx::query(
+                        r#"
+                        update account
+                        set default_profile = $1
+                        where id = $2
+                        "#,
+                    )
+                    .bind(profile_id)
+                    .bind(id),
+                )
+                .await
+                .or_else(|err| Err(AccountError::from_db(err, "Failed to set default profile")))?
+                .rows_affected()
+                <= 0
+            {
+                return Err(AccountError::Other(String::from(
+                    "Failed to find account to update",
+                )));
+            }
+        }
+
+        // Consume the code right before committing, so that only one of several concurrent requests
+        // with the same code can create an account. Dropping the transaction rolls it back.
+        if app_state
+            .valkey_pool
+            .del::<i64, _>(&registration_key)
+            .await?
+            <= 0
+        {
+            return Err(expired());
+        }
+
+        transaction.commit().await.or_else(|err| {
+            Err(AccountError::from_db(
+                err,
+                "Failed to commit account creation",
+            ))
+        })?;
+
+        let response_options = use_response_options()?;
+
+        remove_cookie(&response_options, "regcode")?;
+        remove_cookie(&response_options, "regmail")?;
+
+        app_state
+            .create_session(&request, &response_options, id, username, display_name)
+            .await
+            .or_else(|err| {
+                Err(AccountError::Other(format!(
+                    "Failed to create new session after account creation: {err}"
+                )))
+            })?;
+
+        leptos_actix::redirect("/");
+
+        Ok(())
+    }
 }
 
 /// Route definitions for registration stages.
@@ -24,7 +176,6 @@ pub fn Routes() -> impl MatchNestedRoutes + Clone {
     view! {
         <ParentRoute path=path!("register") view=RegisterContainer>
             <Route path=path!("") view=Register />
-            <Route path=path!("cancel") view=Cancel />
         </ParentRoute>
     }
     .into_inner()
@@ -40,124 +191,17 @@ fn usernameify(display_name: &str) -> String {
         .collect()
 }
 
-/// Fetch the HTTP-only cookie.
-#[server]
+/// Create an account, and optionally its first profile.
+#[server(client = CsrfClient)]
 async fn register_new_user(
     create_profile: Option<String>,
     display_name: Option<String>,
     username: Option<String>,
     bio: Option<String>,
-) -> Result<(), ServerFnError> {
+) -> Result<(), ServerFnError<AccountError>> {
     use self::ssr::*;
 
-    let request: HttpRequest = extract().await?;
-    let email = get_cookie(&request, "regmail");
-    let code = get_cookie(&request, "regcode");
-
-    if email.is_empty() || code.is_empty() {
-        // TODO - Additional checks: valid email and code.
-        return Err(ServerFnError::new("Params are missing or invalid"));
-    }
-
-    let app_state = use_app_state()?;
-
-    // Create a transaction for both creating the account and the profile.
-    let mut transaction = app_state.db_pool.begin().await.or_else(|err| {
-        Err(ServerFnError::new(format!(
-            "Failed to create transaction for account creation: {err}"
-        )))
-    })?;
-
-    let id: Uuid = transaction
-        .fetch_one(
-            sqlx::query(
-                r#"
-                insert into account (email)
-                values ($1)
-                returning id
-                "#,
-            )
-            .bind(&email),
-        )
-        .await
-        .or_else(|err| {
-            Err(ServerFnError::new(format!(
-                "Failed to create transaction for account creation: {err}"
-            )))
-        })?
-        .get(0);
-
-    if create_profile.is_some_and(|c| c == "true") {
-        let profile_id: Uuid = transaction
-            .fetch_one(
-                sqlx::query(
-                    r#"
-                    insert into profile (username, account_id, display_name, bio)
-                    values ($1, $2, $3, $4)
-                    returning id
-                    "#,
-                )
-                .bind(&username)
-                .bind(id)
-                .bind(&display_name)
-                .bind(&bio),
-            )
-            .await
-            .or_else(|err| {
-                Err(ServerFnError::new(format!(
-                    "Failed to create transaction for account creation: {err}"
-                )))
-            })?
-            .get(0);
-
-        if transaction
-            .execute(
-                sqlx::query(
-                    r#"
-                    update account
-                    set default_profile = $1
-                    where id = $2
-                    "#,
-                )
-                .bind(profile_id)
-                .bind(id),
-            )
-            .await
-            .or_else(|err| {
-                Err(ServerFnError::new(format!(
-                    "Failed to create transaction for account creation: {err}"
-                )))
-            })?
-            .rows_affected()
-            <= 0
-        {
-            return Err(ServerFnError::new("Failed to find account to update"));
-        }
-    }
-
-    transaction.commit().await.or_else(|err| {
-        Err(ServerFnError::new(format!(
-            "Failed to commit account creation: {err}"
-        )))
-    })?;
-
-    let response_options = use_response_options()?;
-
-    remove_cookie(&response_options, "regcode")?;
-    remove_cookie(&response_options, "regmail")?;
-
-    app_state
-        .create_session(&response_options, id, username, display_name)
-        .await
-        .or_else(|err| {
-            Err(ServerFnError::new(format!(
-                "Failed to create new session after account creation: {err}"
-            )))
-        })?;
-
-    leptos_actix::redirect("/");
-
-    Ok(())
+    Ok(create_account(create_profile, display_name, username, bio).await?)
 }
 
 #[component]
@@ -173,6 +217,7 @@ pub fn RegisterContainer() -> impl IntoView {
 #[component]
 pub fn Register() -> impl IntoView {
     let register_new_user = ServerAction::<RegisterNewUser>::new();
+    let cancel_registration = ServerAction::<CancelRegistration>::new();
 
     let (email, _) = use_cookie::<String, FromToStringCodec>("regmail");
 
@@ -185,6 +230,19 @@ pub fn Register() -> impl IntoView {
     // Whether the username has been modified from the autogenerated suggestion.
     let username_dirty = RwSignal::new(false);
 
+    // Wait for a pause in typing before checking the username.
+    let username_debounced: Signal<String> = signal_debounced(username, 500.0);
+    let username_availability = Resource::new(
+        move || (create_profile.get(), username_debounced.get()),
+        |(create_profile, username)| async move {
+            if create_profile && !username.is_empty() {
+                Some(check_username(username).await)
+            } else {
+                None
+            }
+        },
+    );
+
     view! {
         <Show
             when=move || email.get().is_some()
@@ -200,7 +258,7 @@ pub fn Register() -> impl IntoView {
                 "You don't have an account yet, or haven't associated this email with your account. Fill in this form to create a new account, or login with your previous email first to associate this email with your existing account."
             </p>
 
-            <ActionForm action=register_new_user>
+            <CsrfActionForm action=register_new_user>
                 <fieldset class="p-2 my-2 border-2 border-slate-500">
                     <legend class="text-xl font-bold">Account</legend>
                     <div class="pb-2">
@@ -214,6 +272,7 @@ pub fn Register() -> impl IntoView {
                             // Doesn't need to be re-sent.
                             disabled
                         />
+                        <ShowFieldError action=register_new_user field="email" />
                         <p>
                             "To change the email you register as, "
                             <ANorm href="/auth/email">"verify with a different email"</ANorm>
@@ -314,21 +373,66 @@ pub fn Register() -> impl IntoView {
                             id="username"
                             placeholder="Username"
                             required
-                            minlength="5"
-                            maxlength="20"
-                            pattern="[a-z][a-z0-9]{4,19}"
-                            title="starts with a letter, has only lowercase letters and numbers, and is between 5 and 20 characters long"
+                            minlength=USERNAME_MIN_LEN
+                            maxlength=USERNAME_MAX_LEN
+                            pattern=username_pattern()
+                            title=username_rules()
                             class="p-0.5 border-2 border-slate-300 disabled:border-slate-100"
                             bind:value=username
                             on:input:target=move |ev| {
                                 username_dirty.set(!ev.target().value().is_empty());
                             }
                         />
+                        <Transition>
+                            {move || Suspend::new(async move {
+                                match username_availability.await {
+                                    Some(Ok(UsernameAvailability { problem: None, .. })) => {
+                                        view! {
+                                            <p class="text-green-700">"That username is available."</p>
+                                        }
+                                            .into_any()
+                                    }
+                                    Some(
+                                        Ok(
+                                            UsernameAvailability { problem: Some(problem), suggestions },
+                                        ),
+                                    ) => {
+                                        view! {
+                                            <p class="text-red-700">
+                                                {problem} {(!suggestions.is_empty()).then_some(" Try: ")}
+                                                {suggestions
+                                                    .into_iter()
+                                                    .map(|suggestion| {
+                                                        let pick = suggestion.clone();
+                                                        view! {
+                                                            <button
+                                                                type="button"
+                                                                class="py-0.5 px-2 mr-1 bg-slate-200 hover:bg-slate-400"
+                                                                on:click=move |_| {
+                                                                    username.set(pick.clone());
+                                                                    username_dirty.set(true);
+                                                                }
+                                                            >
+                                                                {suggestion}
+                                                            </button>
+                                                        }
+                                                    })
+                                                    .collect_view()}
+                                            </p>
+                                        }
+                                            .into_any()
+                                    }
+                                    Some(Err(err)) => {
+                                        view! { <ShowServerFnError error=err /> }.into_any()
+                                    }
+                                    None => view! { "" }.into_any(),
+                                }
+                            })}
+                        </Transition>
+                        <ShowFieldError action=register_new_user field="username" />
                         <p>
-                            A username is also used to identify you, but may be abbreviated
-                            compared to the display name. It may be used by others to refer to
-                            you. It must start with a letter, have only lowercase letters and
-                            numbers, and be between 5 and 20 characters long.
+                            "A username is also used to identify you, but may be abbreviated compared to the display name. It may be used by others to refer to you. It must start with a letter, have only lowercase letters and numbers, and be between "
+                            {USERNAME_MIN_LEN} " and " {USERNAME_MAX_LEN} " characters long."
                         </p>
                     </div>
                     <div class="py-2">
@@ -356,40 +460,46 @@ pub fn Register() -> impl IntoView {
                         type="submit"
                         value="Create account"
                     />
+                </div>
+            </CsrfActionForm>
+            <ShowFormStatus action=register_new_user />
+            // A separate form, so cancelling doesn't need the TOS checkbox.
+            <CsrfActionForm action=cancel_registration>
+                <div class="py-2">
                     <input
                         class="py-0.5 px-2 font-bold bg-slate-200 hover:bg-slate-400"
                         type="submit"
-                        formaction="/auth/register/cancel"
                         value="Cancel"
-                        // Can cancel without checking TOS checkbox.
-                        formnovalidate
                     />
                 </div>
-            </ActionForm>
+            </CsrfActionForm>
+            <ShowActionStatus action=cancel_registration />
         </Show>
     }
 }
 
-#[server]
+/// Abandon registration, forgetting the proven email on the server as well as in cookies so it
+/// can't be resumed elsewhere.
+#[server(client = CsrfClient)]
 async fn cancel_registration() -> Result<(), ServerFnError> {
-    use crate::ssr::cookie::*;
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let code = get_cookie(&request, "regcode");
+
+    if !code.is_empty() && code.chars().all(char::is_alphanumeric) {
+        let app_state = use_app_state()?;
+        app_state
+            .valkey_pool
+            .del::<(), _>(key::new_registration(&code))
+            .await?;
+    }
+
     let response_options = use_response_options()?;
     remove_cookie(&response_options, "regcode")?;
-    // TODO - Delete server saved registration info.
-    Ok(())
-}
-
-#[component]
-pub fn Cancel() -> impl IntoView {
-    let (_, set_email) = use_cookie::<String, FromToStringCodec>("regmail");
+    remove_cookie(&response_options, "regmail")?;
 
-    set_email(None);
+    leptos_actix::redirect("/auth");
 
-    view! {
-        <Await future=cancel_registration() let:_>
-            <p>
-                "Registration cancelled. If you are not automatically redirected, you can navigate away from this page now."
-            </p>
-        </Await>
-    }
+    Ok(())
 }
@@ -0,0 +1,153 @@
+/// The second step of email or provider login for accounts with an authenticator app set up.
+use super::email::ChallengeAnswer;
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos_router::components::*;
+use leptos_router::{MatchNestedRoutes, path};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use super::super::email::ssr::{claim_login_attempt, reject_login_attempt};
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::cookie::*;
+    pub use crate::ssr::key;
+    pub use crate::ssr::login::{SECOND_FACTOR_TOKEN_LEN, log_in_account};
+    pub use crate::ssr::rate_limit::*;
+    pub use crate::ssr::request::client_ip;
+    pub use crate::ssr::totp::check_second_factor;
+    pub use crate::ssr::uuid_codec::decode_uuid;
+
+    pub use actix_web::HttpRequest;
+    pub use fred::prelude::{HashesInterface, KeysInterface};
+    pub use leptos_actix::extract;
+    pub use std::collections::HashMap;
+}
+
+/// Route definitions for the second factor step.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("totp") view=SecondFactor /> }.into_inner()
+}
+
+/// Check a code from the authenticator app, or a recovery code, and finish logging in if it's
+/// correct. Wrong codes count against the login like wrong login codes do.
+#[server(client = CsrfClient)]
+async fn answer_second_factor(code: String) -> Result<ChallengeAnswer, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let token = get_cookie(&request, "tfchal");
+    if token.len() != SECOND_FACTOR_TOKEN_LEN || !token.chars().all(char::is_alphanumeric) {
+        return Ok(ChallengeAnswer::Expired);
+    }
+
+    let app_state = use_app_state()?;
+    LOGIN_ANSWER_PER_IP
+        .check(&app_state.valkey_pool, &client_ip(&request))
+        .await?;
+
+    let key = key::second_factor(&token);
+    let Some(data) = app_state
+        .valkey_pool
+        .hgetall::<Option<HashMap<String, String>>, _>(&key)
+        .await?
+    else {
+        return Ok(ChallengeAnswer::Expired);
+    };
+    let Some(account_id) = data
+        .get("account")
+        .and_then(|account_id| decode_uuid(account_id).ok())
+    else {
+        return Ok(ChallengeAnswer::Expired);
+    };
+    let Some(attempts_left) = claim_login_attempt(&app_state, &key).await? else {
+        return Ok(ChallengeAnswer::Expired);
+    };
+
+    if !check_second_factor(&app_state, account_id, &code).await? {
+        return reject_login_attempt(&app_state, &key, attempts_left).await;
+    }
+
+    // If it's already gone, the login expired or was finished in the meantime.
+    if app_state.valkey_pool.del::<i64, _>(&key).await? <= 0 {
+        return Ok(ChallengeAnswer::Expired);
+    }
+
+    let response_options = use_response_options()?;
+    remove_cookie(&response_options, "tfchal")?;
+
+    log_in_account(&app_state, &request, &response_options, account_id).await?;
+    Ok(ChallengeAnswer::Accepted)
+}
+
+/// Page asking for the second factor after the login code was accepted.
+#[component]
+pub fn SecondFactor() -> impl IntoView {
+    let answer_second_factor = ServerAction::<AnswerSecondFactor>::new();
+
+    view! {
+        <CsrfActionForm action=answer_second_factor>
+            <p>
+                "Your account has an authenticator app set up. Enter the code it shows, or one of your recovery codes if you don't have the app."
+            </p>
+
+            <div class="flex gap-2">
+                <label for="code">Code:</label>
+                <input
+                    type="text"
+                    name="code"
+                    id="code"
+                    placeholder="code"
+                    class="px-1 h-full bg-gray-200 border border-gray-500 invalid:border-red-500"
+                    required
+                    autofocus
+                    autocomplete="one-time-code"
+                    value=""
+                />
+                <input
+                    type="submit"
+                    value="Submit code"
+                    class="px-2 h-full bg-green-200 hover:bg-green-300"
+                />
+                <Show
+                    when=move || { !answer_second_factor.pending().get() }
+                    fallback=move || view! { <Spinner /> }
+                >
+                    {move || {
+                        match answer_second_factor.value().get() {
+                            Some(Err(err)) => view! { <ShowServerFnError error=err /> }.into_any(),
+                            Some(Ok(ChallengeAnswer::Accepted)) => {
+                                view! {
+                                    "Code accepted. You will be automatically redirected shortly."
+                                }
+                                    .into_any()
+                            }
+                            Some(
+                                Ok(ChallengeAnswer::Rejected { attempts_left }),
+                            ) if attempts_left > 0 => {
+                                view! {
+                                    "Code rejected. Try again. "
+                                    {attempts_left}
+                                    {if attempts_left == 1 { " attempt" } else { " attempts" }}
+                                    " left."
+                                }
+                                    .into_any()
+                            }
+                            Some(Ok(_)) => {
+                                view! {
+                                    "This login expired or had too many wrong codes. "
+                                    <ANorm href="/auth/email">"Start over"</ANorm>
+                                    "."
+                                }
+                                    .into_any()
+                            }
+                            None => view! { "" }.into_any(),
+                        }
+                    }}
+                </Show>
+            </div>
+        </CsrfActionForm>
+    }
+}
@@ -0,0 +1,207 @@
+/// Undoing a change of primary email, or an added secondary email, from the link sent to the
+/// old or primary address.
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos_router::components::*;
+use leptos_router::hooks::use_query_map;
+use leptos_router::{MatchNestedRoutes, path};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::key;
+    pub use crate::ssr::uuid_codec::decode_uuid;
+
+    pub use fred::prelude::{HashesInterface, KeysInterface};
+    pub use std::collections::HashMap;
+}
+
+/// Route definitions for undoing email changes.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("undo-email-change") view=UndoEmailChange /> }.into_inner()
+}
+
+/// Put back the emails that an account had before a change, remove the passkeys, linked
+/// identities, API tokens and authenticator app added since, and log out every session of the
+/// account in case it was hijacked. Returns the restored primary email.
+#[server(client = CsrfClient)]
+async fn undo_email_change(token: String) -> Result<String, ServerFnError> {
+    use self::ssr::*;
+
+    let expired = || ServerFnError::new("This undo link expired or was already used.");
+
+    if token.is_empty() || !token.chars().all(char::is_alphanumeric) {
+        return Err(expired());
+    }
+
+    let app_state = use_app_state()?;
+    let key = key::email_change_undo(&token);
+    let change = app_state
+        .valkey_pool
+        .hgetall::<Option<HashMap<String, String>>, _>(&key)
+        .await?
+        .ok_or_else(expired)?;
+    let (Some(encoded_account_id), Some(old_email), Some(new_email)) =
+        (change.get("acctid"), change.get("old"), change.get("new"))
+    else {
+        return Err(expired());
+    };
+    let account_id = decode_uuid(encoded_account_id)
+        .or_else(|err| Err(ServerFnError::new(format!("Bad account ID: {err}"))))?;
+    // The secondary emails from before the change. Undo links sent before these were saved only
+    // remove the new email.
+    let secondary_emails = change
+        .get("secondary")
+        .map(|secondary| serde_json::from_str::<Vec<String>>(secondary))
+        .transpose()
+        .or_else(|err| Err(ServerFnError::new(format!("Bad secondary emails: {err}"))))?;
+    // When the change was made. Undo links sent before this was saved leave other ways to log in
+    // alone.
+    let changed = change
+        .get("changed")
+        .map(|changed| changed.parse::<i64>())
+        .transpose()
+        .or_else(|err| Err(ServerFnError::new(format!("Bad change time: {err}"))))?;
+
+    let mut transaction = app_state.db_pool.begin().await.or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to create transaction for undoing email change: {err}"
+        )))
+    })?;
+
+    // Restore the old email even if the account's email changed again since, as the later change
+    // could have been made by whoever took over the account. For the same reason, secondary emails
+    // added since are removed, while ones removed since stay removed.
+    if sqlx::query(
+        r#"
+        update account
+        set
+          email = $2::email,
+          secondary_email = array(
+            select secondary
+            from unnest(secondary_email) with ordinality as current_email (secondary, position)
+            where secondary <> all(array[$2::email, $3::email])
+              and ($4::text[] is null or secondary = any($4::email[]))
+            order by position
+          )
+        where id = $1
+        "#,
+    )
+    .bind(account_id)
+    .bind(old_email)
+    .bind(new_email)
+    .bind(secondary_emails)
+    .execute(&mut *transaction)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to restore {old_email}: {err}"
+        )))
+    })?
+    .rows_affected()
+        == 0
+    {
+        return Err(ServerFnError::new("That account no longer exists."));
+    }
+
+    // Whoever took over the account could have added their own ways to log in since, which would
+    // outlast getting the emails back.
+    if let Some(changed) = changed {
+        sqlx::query(
+            r#"
+            with
+              since as (select to_timestamp($2)::timestamp as changed_at),
+              passkeys as (
+                delete from webauthn_credential
+                where account_id = $1 and created_at >= (select changed_at from since)
+              ),
+              identities as (
+                delete from linked_identity
+                where account_id = $1 and created_at >= (select changed_at from since)
+              ),
+              tokens as (
+                delete from api_token
+                where account_id = $1 and created_at >= (select changed_at from since)
+              )
+            delete from totp_credential
+            where account_id = $1 and created_at >= (select changed_at from since)
+            "#,
+        )
+        .bind(account_id)
+        .bind(changed as f64)
+        .execute(&mut *transaction)
+        .await
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Failed to remove ways to log in added since the change: {err}"
+            )))
+        })?;
+    }
+
+    transaction.commit().await.or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to commit undoing email change: {err}"
+        )))
+    })?;
+
+    app_state.valkey_pool.del::<(), _>(&key).await?;
+    app_state
+        .valkey_pool
+        .del::<(), _>(key::email_verification(encoded_account_id))
+        .await?;
+    app_state.end_all_sessions(account_id).await?;
+
+    Ok(old_email.clone())
+}
+
+/// Landing page for undo links. Like login links, this takes a click so that email scanners
+/// opening the link don't trigger it.
+#[component]
+pub fn UndoEmailChange() -> impl IntoView {
+    let query = use_query_map();
+    let token = move || query.read().get("token").unwrap_or_default();
+
+    let undo_email_change = ServerAction::<UndoEmailChange>::new();
+
+    view! {
+        <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
+            <legend class="m-2 text-2xl font-bold">Undo email change</legend>
+            <p>
+                "This puts back your primary email, removes any emails, passkeys, linked accounts, API tokens and authenticator app added to your account since the change, and logs out every session of your account."
+            </p>
+            <CsrfActionForm action=undo_email_change>
+                <input type="hidden" name="token" value=token />
+                <div class="py-2">
+                    <input
+                        type="submit"
+                        value="Undo email change"
+                        class="py-0.5 px-2 font-bold bg-red-200 hover:bg-red-400"
+                    />
+                </div>
+            </CsrfActionForm>
+            <Show
+                when=move || { !undo_email_change.pending().get() }
+                fallback=move || view! { <Spinner /> }
+            >
+                {move || match undo_email_change.value().get() {
+                    Some(Err(err)) => view! { <ShowServerFnError error=err /> }.into_any(),
+                    Some(Ok(email)) => {
+                        view! {
+                            <p>
+                                "Your primary email is " {email}
+                                " again, anything added to your account since the change was removed, and every session of your account was logged out. "
+                                <ANorm href="/auth">"Log in"</ANorm>
+                                " to check your other emails and profiles."
+                            </p>
+                        }
+                            .into_any()
+                    }
+                    None => view! { "" }.into_any(),
+                }}
+            </Show>
+        </fieldset>
+    }
+}
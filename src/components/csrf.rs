@@ -0,0 +1,99 @@
+/// Protection against cross-site request forgery. Every server function request has to carry the
+/// token from the CSRF cookie, which other sites can't read: in a header when sent by
+/// CsrfClient, or in a hidden field when a CsrfActionForm is submitted before the page hydrates.
+/// The server side check is in `crate::ssr::csrf`.
+use leptos::prelude::*;
+use leptos::server_fn::ServerFn;
+use leptos::server_fn::client::Client;
+use leptos::server_fn::client::browser::BrowserClient;
+use leptos::server_fn::codec::PostUrl;
+use leptos::server_fn::request::ClientReq;
+use leptos::server_fn::request::browser::BrowserRequest;
+use leptos::server_fn::response::browser::BrowserResponse;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+pub const CSRF_COOKIE: &str = "csrf";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+/// Form field with the token, for forms submitted without going through CsrfClient.
+pub const CSRF_FIELD: &str = "csrf_token";
+
+/// Get the CSRF token for the current page.
+#[cfg(feature = "ssr")]
+fn csrf_token() -> String {
+    use crate::ssr::csrf::CsrfToken;
+    use actix_web::HttpMessage;
+
+    use_context::<leptos_actix::Request>()
+        .and_then(|request| {
+            request
+                .extensions()
+                .get::<CsrfToken>()
+                .map(|token| token.0.clone())
+        })
+        .unwrap_or_default()
+}
+
+/// Get the CSRF token for the current page.
+#[cfg(feature = "hydrate")]
+fn csrf_token() -> String {
+    use wasm_bindgen::JsCast;
+
+    document()
+        .dyn_into::<web_sys::HtmlDocument>()
+        .ok()
+        .and_then(|document| document.cookie().ok())
+        .unwrap_or_default()
+        .split(';')
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == CSRF_COOKIE)
+        .map(|(_, value)| value.to_string())
+        .unwrap_or_default()
+}
+
+/// Get the CSRF token for the current page.
+#[cfg(not(any(feature = "ssr", feature = "hydrate")))]
+fn csrf_token() -> String {
+    String::new()
+}
+
+/// Server function client that sends the CSRF token along with each request. Every server
+/// function that isn't a GET has to use it, or the server will refuse its requests.
+pub struct CsrfClient;
+
+impl<CustErr> Client<CustErr> for CsrfClient {
+    type Request = BrowserRequest;
+    type Response = BrowserResponse;
+
+    fn send(
+        request: Self::Request,
+    ) -> impl Future<Output = Result<Self::Response, ServerFnError<CustErr>>> + Send {
+        request.headers().set(CSRF_HEADER, &csrf_token());
+        <BrowserClient as Client<CustErr>>::send(request)
+    }
+}
+
+/// ActionForm that also works with the CSRF check when it's submitted before the page hydrates.
+/// Once hydrated, the form is sent by the server function's client, which adds the token itself.
+#[component]
+pub fn CsrfActionForm<ServFn>(
+    /// The action from which to build the form.
+    action: ServerAction<ServFn>,
+    /// Component children; should include the HTML of the form elements.
+    children: Children,
+) -> impl IntoView
+where
+    ServFn: DeserializeOwned + ServerFn<InputEncoding = PostUrl> + Clone + Send + Sync + 'static,
+    <<ServFn::Client as Client<ServFn::Error>>::Request as ClientReq<ServFn::Error>>::FormData:
+        From<leptos::web_sys::FormData>,
+    ServFn::Output: Send + Sync + 'static,
+    ServFn::Error: Send + Sync + 'static,
+{
+    view! {
+        <ActionForm action>
+            <input type="hidden" name=CSRF_FIELD value=csrf_token() />
+            {children()}
+        </ActionForm>
+    }
+}
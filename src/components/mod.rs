@@ -1,3 +1,10 @@
+pub mod account_error;
 pub mod app;
 pub mod auth;
+pub mod csrf;
+pub mod passkey;
+pub mod public_profile;
+pub mod session;
+pub mod settings;
 pub mod ui;
+pub mod username;
@@ -0,0 +1,75 @@
+/// Talking to the browser's authenticators for passkeys (WebAuthn). The server side of each
+/// ceremony lives with the page that uses it.
+use leptos::prelude::*;
+use webauthn_rs_proto::{
+    CreationChallengeResponse, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse,
+};
+
+/// Have the browser create a new passkey for the given registration options.
+#[cfg(feature = "hydrate")]
+pub async fn create_credential(
+    options: CreationChallengeResponse,
+) -> Result<RegisterPublicKeyCredential, ServerFnError> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let promise = window()
+        .navigator()
+        .credentials()
+        .create_with_options(&options.into())
+        .map_err(browser_error)?;
+    let credential = JsFuture::from(promise)
+        .await
+        .map_err(browser_error)?
+        .dyn_into::<web_sys::PublicKeyCredential>()
+        .map_err(|_| ServerFnError::new("The browser didn't create a passkey."))?;
+    Ok(credential.into())
+}
+
+/// Have the browser sign the given login challenge with one of the user's passkeys.
+#[cfg(feature = "hydrate")]
+pub async fn get_credential(
+    options: RequestChallengeResponse,
+) -> Result<PublicKeyCredential, ServerFnError> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let promise = window()
+        .navigator()
+        .credentials()
+        .get_with_options(&options.into())
+        .map_err(browser_error)?;
+    let credential = JsFuture::from(promise)
+        .await
+        .map_err(browser_error)?
+        .dyn_into::<web_sys::PublicKeyCredential>()
+        .map_err(|_| ServerFnError::new("The browser didn't use a passkey."))?;
+    Ok(credential.into())
+}
+
+/// Describe an exception from the WebAuthn API, most often the user cancelling.
+#[cfg(feature = "hydrate")]
+fn browser_error(err: wasm_bindgen::JsValue) -> ServerFnError {
+    let message = js_sys::Reflect::get(&err, &"message".into())
+        .ok()
+        .and_then(|message| message.as_string())
+        .unwrap_or_else(|| format!("{err:?}"));
+    ServerFnError::new(format!("The passkey wasn't used: {message}"))
+}
+
+/// Passkeys need the browser, so this only fails outside of it.
+#[cfg(not(feature = "hydrate"))]
+pub async fn create_credential(
+    _options: CreationChallengeResponse,
+) -> Result<RegisterPublicKeyCredential, ServerFnError> {
+    Err(ServerFnError::new("Passkeys only work in the browser."))
+}
+
+/// Passkeys need the browser, so this only fails outside of it.
+#[cfg(not(feature = "hydrate"))]
+pub async fn get_credential(
+    _options: RequestChallengeResponse,
+) -> Result<PublicKeyCredential, ServerFnError> {
+    Err(ServerFnError::new("Passkeys only work in the browser."))
+}
@@ -0,0 +1,177 @@
+/// Public profile pages, which anyone can see without logging in.
+use crate::components::app::NotFound;
+use crate::components::csrf::CsrfClient;
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos_meta::Title;
+use leptos_router::SsrMode;
+use leptos_router::components::*;
+use leptos_router::hooks::use_params_map;
+use leptos_router::{MatchNestedRoutes, path};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::username::find_renamed_profile;
+}
+
+/// Route definitions for profile pages.
+#[component(transparent)]
+pub fn PublicProfileRoutes() -> impl MatchNestedRoutes + Clone {
+    // Rendered all at once, so the status code can depend on whether the profile exists.
+    view! { <Route path=path!("u/:username") view=PublicProfile ssr=SsrMode::Async /> }.into_inner()
+}
+
+/// A profile as anyone can see it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PublicProfileInfo {
+    pub username: String,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    /// Date the profile was created.
+    pub joined: String,
+}
+
+/// What there is at a profile URL.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ProfileLookup {
+    Found(PublicProfileInfo),
+    /// The profile was renamed. Has its current username.
+    Renamed(String),
+    NotFound,
+}
+
+/// Get a profile by username, or where it went if it was renamed.
+#[server(client = CsrfClient)]
+async fn get_public_profile(username: String) -> Result<ProfileLookup, ServerFnError> {
+    use self::ssr::*;
+
+    let app_state = use_app_state()?;
+
+    // Hide profiles of accounts waiting to be deleted, as if they were gone already.
+    let profile = sqlx::query_as::<_, (String, Option<String>, Option<String>, String)>(
+        r#"
+        select
+          profile.username::text,
+          profile.display_name,
+          profile.bio,
+          profile.created_at::date::text
+        from
+          profile
+          join account on profile.account_id = account.id
+        where profile.username = $1
+          and account.deletion_requested_at is null
+        "#,
+    )
+    .bind(&username)
+    .fetch_optional(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get profile from DB: {err}"
+        )))
+    })?;
+
+    if let Some((username, display_name, bio, joined)) = profile {
+        return Ok(ProfileLookup::Found(PublicProfileInfo {
+            username,
+            display_name,
+            bio,
+            joined,
+        }));
+    }
+
+    Ok(
+        match find_renamed_profile(&app_state.db_pool, &username)
+            .await
+            .or_else(|err| {
+                Err(ServerFnError::new(format!(
+                    "Couldn't look up old username: {err}"
+                )))
+            })? {
+            Some(current) => ProfileLookup::Renamed(current),
+            None => ProfileLookup::NotFound,
+        },
+    )
+}
+
+/// Send visitors of an old profile URL to the current one, permanently so search engines and
+/// bookmarks update.
+#[component]
+fn ProfileMoved(path: String) -> impl IntoView {
+    // Like the status code of NotFound, this only works during server-side rendering. Navigating
+    // here within the app redirects on the client instead.
+    #[cfg(feature = "ssr")]
+    {
+        use actix_web::http::{StatusCode, header};
+
+        let resp = expect_context::<leptos_actix::ResponseOptions>();
+        resp.set_status(StatusCode::MOVED_PERMANENTLY);
+        if let Ok(location) = header::HeaderValue::from_str(&path) {
+            resp.insert_header(header::LOCATION, location);
+        }
+
+        view! { <p>"This profile has moved to " <ANorm href=path.clone()>{path.clone()}</ANorm> "."</p> }
+        .into_any()
+    }
+    #[cfg(not(feature = "ssr"))]
+    view! { <Redirect path=path /> }.into_any()
+}
+
+/// Page showing a profile.
+#[component]
+pub fn PublicProfile() -> impl IntoView {
+    let params = use_params_map();
+    let profile = Resource::new(
+        move || params.read().get("username").unwrap_or_default(),
+        get_public_profile,
+    );
+
+    view! {
+        <Suspense fallback=move || {
+            view! { <Spinner /> }
+        }>
+            {move || Suspend::new(async move {
+                match profile.await {
+                    Ok(ProfileLookup::Found(profile)) => {
+                        let title = match &profile.display_name {
+                            Some(display_name) => format!("{display_name} (@{})", profile.username),
+                            None => format!("@{}", profile.username),
+                        };
+                        view! {
+                            <Title text=title />
+                            <h1 class="text-4xl font-bold">
+                                {profile
+                                    .display_name
+                                    .clone()
+                                    .unwrap_or_else(|| profile.username.clone())}
+                            </h1>
+                            <p class="text-slate-600">
+                                "@" {profile.username} " · Joined " {profile.joined}
+                            </p>
+                            {profile
+                                .bio
+                                .map(|bio| {
+                                    view! { <p class="my-2 whitespace-pre-wrap">{bio}</p> }
+                                })}
+                            // Nothing stores quests or activity yet, so these are always empty
+                            // until something does.
+                            <h2 class="mt-4 text-2xl font-bold">"Quests"</h2>
+                            <p class="text-slate-600">"No quests yet."</p>
+                            <h2 class="mt-4 text-2xl font-bold">"Recent activity"</h2>
+                            <p class="text-slate-600">"No activity yet."</p>
+                        }
+                            .into_any()
+                    }
+                    Ok(ProfileLookup::Renamed(username)) => {
+                        view! { <ProfileMoved path=format!("/u/{username}") /> }.into_any()
+                    }
+                    Ok(ProfileLookup::NotFound) => view! { <NotFound /> }.into_any(),
+                    Err(err) => view! { <ShowServerFnError error=err /> }.into_any(),
+                }
+            })}
+        </Suspense>
+    }
+}
@@ -0,0 +1,88 @@
+/// Who is logged in, available to every component through context, and guards for pages that
+/// need a login.
+use crate::components::csrf::CsrfClient;
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos_router::components::Redirect;
+use leptos_router::hooks::use_location;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::app_state::*;
+
+    pub use actix_web::HttpRequest;
+    pub use leptos_actix::extract;
+}
+
+/// The logged in user, as far as the UI is concerned.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CurrentSession {
+    /// Username of the profile being acted as. Empty in reader mode.
+    pub username: String,
+    /// Display name of the profile being acted as. May be empty even outside reader mode.
+    pub display_name: String,
+    pub reader_mode: bool,
+}
+
+/// Resource with the current session, or None if not logged in.
+pub type CurrentSessionResource = Resource<Result<Option<CurrentSession>, ServerFnError>>;
+
+/// Get the current session, if any.
+#[server(client = CsrfClient)]
+pub async fn get_current_session() -> Result<Option<CurrentSession>, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+
+    match app_state.get_session(request).await {
+        Some(Ok(session)) => Ok(Some(CurrentSession {
+            reader_mode: session.username.is_empty(),
+            username: session.username,
+            display_name: session.display_name,
+        })),
+        // An expired or broken session is the same as being logged out, as far as the UI is
+        // concerned.
+        Some(Err(_)) | None => Ok(None),
+    }
+}
+
+/// Provide the current session to all children through context. Must be used inside the Router.
+#[component]
+pub fn CurrentSessionProvider(children: Children) -> impl IntoView {
+    let location = use_location();
+    // Logging in or out always ends in a redirect, so refetching on navigation keeps this fresh.
+    let session: CurrentSessionResource =
+        Resource::new(move || location.pathname.get(), |_| get_current_session());
+    provide_context(session);
+
+    children()
+}
+
+/// Get the current session resource provided by CurrentSessionProvider.
+pub fn use_current_session() -> CurrentSessionResource {
+    expect_context::<CurrentSessionResource>()
+}
+
+/// Only show children to logged in users, and redirect everyone else to log in.
+#[component]
+pub fn RequireLogin(children: ChildrenFn) -> impl IntoView {
+    let session = use_current_session();
+    let children = StoredValue::new(children);
+
+    view! {
+        <Transition fallback=move || {
+            view! { <Spinner /> }
+        }>
+            {move || Suspend::new(async move {
+                match session.await {
+                    Ok(Some(_)) => children.read_value()().into_any(),
+                    Ok(None) => view! { <Redirect path="/auth" /> }.into_any(),
+                    Err(err) => view! { <ShowServerFnError error=err /> }.into_any(),
+                }
+            })}
+        </Transition>
+    }
+}
@@ -0,0 +1,501 @@
+/// Exporting an account's data, and deleting the account.
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos::server_fn::ServerFn;
+use leptos::server_fn::codec::GetUrl;
+use leptos_router::components::*;
+use leptos_router::{MatchNestedRoutes, path};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::account_deletion::ACCOUNT_DELETION_GRACE_DAYS;
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::cookie::*;
+
+    pub use actix_web::HttpRequest;
+    pub use actix_web::http::header::{CONTENT_DISPOSITION, HeaderValue};
+    pub use leptos_actix::extract;
+}
+
+/// Route definitions for account management.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("account") view=AccountSettings /> }.into_inner()
+}
+
+/// A profile, as included in an account's data export.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExportedProfile {
+    pub username: String,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub created_at: String,
+}
+
+/// A session, as included in an account's data export.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExportedSession {
+    /// Unix timestamp of when the session was created.
+    pub created_at: i64,
+    /// Unix timestamp of when the session was last used.
+    pub last_seen: i64,
+    pub user_agent: String,
+    pub ip: String,
+}
+
+/// A passkey, as included in an account's data export. The key material itself is left out.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExportedPasskey {
+    pub name: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+/// An identity at a login provider linked to the account, as included in its data export.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExportedLinkedLogin {
+    pub provider: String,
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+/// An API token, as included in an account's data export. The token's hash is left out.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExportedApiToken {
+    pub name: String,
+    /// Username of the profile the token acts as.
+    pub profile: String,
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+/// Everything stored about an account, for its owner to download.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AccountExport {
+    pub email: String,
+    pub secondary_emails: Vec<String>,
+    pub created_at: String,
+    pub ask_for_profile_on_login: bool,
+    /// Username of the default profile. None is reader mode.
+    pub default_profile: Option<String>,
+    pub deletion_requested_at: Option<String>,
+    /// Whether an authenticator app is set up. Its secret and the recovery codes are left out.
+    pub two_factor_enabled: bool,
+    pub profiles: Vec<ExportedProfile>,
+    pub sessions: Vec<ExportedSession>,
+    pub passkeys: Vec<ExportedPasskey>,
+    pub linked_logins: Vec<ExportedLinkedLogin>,
+    pub api_tokens: Vec<ExportedApiToken>,
+}
+
+/// Download everything stored about the logged in account, as JSON. This is a GET endpoint so
+/// the browser can save it as a file from a plain link.
+#[server(endpoint = "export_account_data", input = GetUrl)]
+async fn export_account_data() -> Result<AccountExport, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let (
+        email,
+        secondary_emails,
+        created_at,
+        ask_for_profile_on_login,
+        default_profile,
+        deletion_requested_at,
+        two_factor_enabled,
+    ) = sqlx::query_as::<
+        _,
+        (
+            String,
+            Vec<String>,
+            String,
+            bool,
+            Option<String>,
+            Option<String>,
+            bool,
+        ),
+    >(
+        r#"
+        select
+          account.email::text,
+          coalesce(account.secondary_email::text[], '{}'),
+          account.created_at::text,
+          account.ask_for_profile_on_login,
+          profile.username::text,
+          account.deletion_requested_at::text,
+          exists (select 1 from totp_credential where account_id = account.id)
+        from
+          account
+          left join profile on account.default_profile = profile.id
+        where account.id = $1
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_one(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get account from DB: {err}"
+        )))
+    })?;
+
+    let profiles = sqlx::query_as::<_, (String, Option<String>, Option<String>, String)>(
+        r#"
+        select username::text, display_name, bio, created_at::text
+        from profile
+        where account_id = $1
+        order by created_at
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get profiles from DB: {err}"
+        )))
+    })?
+    .into_iter()
+    .map(
+        |(username, display_name, bio, created_at)| ExportedProfile {
+            username,
+            display_name,
+            bio,
+            created_at,
+        },
+    )
+    .collect();
+
+    let sessions = app_state
+        .list_sessions(session.account_id)
+        .await?
+        .into_iter()
+        .map(|details| ExportedSession {
+            created_at: details.created_at,
+            last_seen: details.last_seen,
+            user_agent: details.user_agent,
+            ip: details.ip,
+        })
+        .collect();
+
+    let passkeys = sqlx::query_as::<_, (String, String, Option<String>)>(
+        r#"
+        select name, created_at::text, last_used_at::text
+        from webauthn_credential
+        where account_id = $1
+        order by created_at
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get passkeys from DB: {err}"
+        )))
+    })?
+    .into_iter()
+    .map(|(name, created_at, last_used_at)| ExportedPasskey {
+        name,
+        created_at,
+        last_used_at,
+    })
+    .collect();
+
+    let linked_logins = sqlx::query_as::<
+        _,
+        (
+            String,
+            String,
+            String,
+            Option<String>,
+            String,
+            Option<String>,
+        ),
+    >(
+        r#"
+        select provider, issuer, subject, email, created_at::text, last_used_at::text
+        from linked_identity
+        where account_id = $1
+        order by created_at
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get linked logins from DB: {err}"
+        )))
+    })?
+    .into_iter()
+    .map(
+        |(provider, issuer, subject, email, created_at, last_used_at)| ExportedLinkedLogin {
+            provider,
+            issuer,
+            subject,
+            email,
+            created_at,
+            last_used_at,
+        },
+    )
+    .collect();
+
+    let api_tokens = sqlx::query_as::<_, (String, String, Vec<String>, String, Option<String>)>(
+        r#"
+        select
+          api_token.name,
+          profile.username::text,
+          api_token.scopes,
+          api_token.created_at::text,
+          api_token.last_used_at::text
+        from
+          api_token
+          join profile on api_token.profile_id = profile.id
+        where api_token.account_id = $1
+        order by api_token.created_at
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get API tokens from DB: {err}"
+        )))
+    })?
+    .into_iter()
+    .map(
+        |(name, profile, scopes, created_at, last_used_at)| ExportedApiToken {
+            name,
+            profile,
+            scopes,
+            created_at,
+            last_used_at,
+        },
+    )
+    .collect();
+
+    let response_options = use_response_options()?;
+    response_options.insert_header(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static(r#"attachment; filename="account-data.json""#),
+    );
+
+    Ok(AccountExport {
+        email,
+        secondary_emails,
+        created_at,
+        ask_for_profile_on_login,
+        default_profile,
+        deletion_requested_at,
+        two_factor_enabled,
+        profiles,
+        sessions,
+        passkeys,
+        linked_logins,
+        api_tokens,
+    })
+}
+
+/// Whether the account is scheduled for deletion, and the rules around it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AccountDeletion {
+    /// Date the account will be deleted on, if its deletion was requested.
+    pub scheduled_for: Option<String>,
+    /// How many days after the request the account is deleted.
+    pub grace_days: i64,
+}
+
+/// Get whether the logged in account is scheduled for deletion.
+#[server(client = CsrfClient)]
+async fn get_account_deletion() -> Result<AccountDeletion, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let (scheduled_for,) = sqlx::query_as::<_, (Option<String>,)>(
+        r#"
+        select (deletion_requested_at + make_interval(days => $2))::date::text
+        from account
+        where id = $1
+        "#,
+    )
+    .bind(session.account_id)
+    .bind(ACCOUNT_DELETION_GRACE_DAYS as i32)
+    .fetch_one(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get account from DB: {err}"
+        )))
+    })?;
+
+    Ok(AccountDeletion {
+        scheduled_for,
+        grace_days: ACCOUNT_DELETION_GRACE_DAYS,
+    })
+}
+
+/// Schedule the logged in account for deletion once the grace period is over, and log out every
+/// session of it. Logging in again during the grace period still works, to cancel the deletion.
+#[server(client = CsrfClient)]
+async fn request_account_deletion() -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request.clone()).await?;
+
+    sqlx::query(
+        r#"
+        update account
+        set deletion_requested_at = now()
+        where id = $1
+          and deletion_requested_at is null
+        "#,
+    )
+    .bind(session.account_id)
+    .execute(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to schedule account deletion: {err}"
+        )))
+    })?;
+
+    let response_options = use_response_options()?;
+    app_state.end_all_sessions(session.account_id).await?;
+    app_state.end_session(request, &response_options).await?;
+    leptos_actix::redirect("/");
+
+    Ok(())
+}
+
+/// Keep the logged in account after all, if its deletion was requested.
+#[server(client = CsrfClient)]
+async fn cancel_account_deletion() -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    sqlx::query(
+        r#"
+        update account
+        set deletion_requested_at = null
+        where id = $1
+        "#,
+    )
+    .bind(session.account_id)
+    .execute(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to cancel account deletion: {err}"
+        )))
+    })?;
+
+    Ok(())
+}
+
+/// Page for exporting the current account's data and deleting the account.
+#[component]
+pub fn AccountSettings() -> impl IntoView {
+    let request_account_deletion = ServerAction::<RequestAccountDeletion>::new();
+    let cancel_account_deletion = ServerAction::<CancelAccountDeletion>::new();
+    let deletion = Resource::new(
+        move || cancel_account_deletion.version().get(),
+        |_| get_account_deletion(),
+    );
+
+    view! {
+        <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
+            <legend class="m-2 text-2xl font-bold">"Your data"</legend>
+            <p>
+                "Download everything stored about your account, including your emails, profiles, sessions, passkeys, linked logins, and API tokens, as a JSON file."
+            </p>
+            <a
+                href=ExportAccountData::PATH
+                download="account-data.json"
+                rel="external"
+                class="inline-block py-0.5 px-2 my-2 bg-slate-200 hover:bg-slate-400"
+            >
+                "Download my data"
+            </a>
+        </fieldset>
+        <fieldset class="px-2 pt-1 pb-2 my-2 border-2 border-slate-500">
+            <legend class="m-2 text-2xl font-bold">"Delete account"</legend>
+            <ShowActionStatus
+                action=cancel_account_deletion
+                success="Account deletion cancelled."
+            />
+            <ShowActionStatus
+                action=request_account_deletion
+                success="Account deletion scheduled."
+            />
+            <Transition fallback=move || {
+                view! { <Spinner /> }
+            }>
+                {move || Suspend::new(async move {
+                    match deletion.await {
+                        Ok(AccountDeletion { scheduled_for: Some(deletion_date), .. }) => {
+                            view! {
+                                <p>
+                                    "Your account and all its profiles will be deleted on "
+                                    <span class="font-bold">{deletion_date}</span>
+                                    ". Until then, you can change your mind."
+                                </p>
+                                <CsrfActionForm action=cancel_account_deletion>
+                                    <input
+                                        type="submit"
+                                        value="Keep my account"
+                                        class="py-0.5 px-2 my-2 font-bold bg-green-200 hover:bg-green-400"
+                                    />
+                                </CsrfActionForm>
+                            }
+                                .into_any()
+                        }
+                        Ok(AccountDeletion { scheduled_for: None, grace_days }) => {
+                            view! {
+                                <p>
+                                    "Deleting your account also deletes all its profiles. You'll be logged out everywhere, and the account will be deleted for good after "
+                                    {grace_days}
+                                    " days. To cancel, log in again before then and click \"Keep my account\" here in your account settings."
+                                </p>
+                                <CsrfActionForm action=request_account_deletion>
+                                    <div class="py-2">
+                                        <label>
+                                            <input type="checkbox" required />
+                                            " I understand my account and all its profiles will be deleted "
+                                        </label>
+                                    </div>
+                                    <input
+                                        type="submit"
+                                        value="Delete my account"
+                                        class="py-0.5 px-2 bg-red-200 hover:bg-red-400"
+                                    />
+                                </CsrfActionForm>
+                            }
+                                .into_any()
+                        }
+                        Err(err) => view! { <ShowServerFnError error=err /> }.into_any(),
+                    }
+                })}
+            </Transition>
+        </fieldset>
+    }
+}
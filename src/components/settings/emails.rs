@@ -0,0 +1,761 @@
+/// Management of the emails an account can log in with.
+use crate::components::account_error::*;
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos_router::components::*;
+use leptos_router::{MatchNestedRoutes, path};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::components::account_error::AccountError;
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::key;
+    pub use crate::ssr::mail;
+    pub use crate::ssr::rate_limit::*;
+    pub use crate::ssr::uuid_codec::encode_uuid;
+
+    pub use actix_web::HttpRequest;
+    pub use fred::prelude::{HashesInterface, KeysInterface, TransactionInterface};
+    pub use leptos_actix::extract;
+    pub use lettre::AsyncTransport;
+    pub use rand::{
+        distributions::{Alphanumeric, DistString},
+        thread_rng,
+    };
+    pub use sqlx::Executor;
+    pub use std::collections::HashMap;
+    pub use uuid::Uuid;
+
+    use super::{
+        MAX_VERIFICATION_ATTEMPTS, VERIFICATION_CODE_EXPIRATION_MIN, VERIFICATION_CODE_LEN,
+    };
+    use leptos::prelude::ServerFnError;
+
+    /// How long the old address can undo a change of primary email.
+    pub const EMAIL_CHANGE_UNDO_DAYS: i64 = 7;
+    pub const UNDO_TOKEN_LEN: usize = 32;
+
+    /// Email a verification code to an address the logged in account wants to add, either as a
+    /// secondary email or to replace the primary one. Only one address can be waiting for
+    /// verification at a time, so this replaces any earlier one.
+    pub async fn send_verification_code(email: String, primary: bool) -> Result<(), AccountError> {
+        let request: HttpRequest = extract().await?;
+        let app_state = use_app_state()?;
+        let session = app_state.require_session(request).await?;
+
+        let address = email
+            .parse::<lettre::address::Address>()
+            .or_else(|_| Err(AccountError::EmailInvalid))?;
+
+        let (already_added,) = sqlx::query_as::<_, (bool,)>(
+            r#"
+            select email = $2::email or $2::email = any(coalesce(secondary_email, '{}'))
+            from account
+            where id = $1
+            "#,
+        )
+        .bind(session.account_id)
+        .bind(&email)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .or_else(|err| Err(AccountError::from_db(err, "Couldn't get account from DB")))?;
+        if already_added {
+            return Err(AccountError::Other(String::from(
+                "That email is already on your account.",
+            )));
+        }
+
+        let account_id = encode_uuid(session.account_id);
+        EMAIL_VERIFICATION_PER_ACCOUNT
+            .check(&app_state.valkey_pool, &account_id)
+            .await?;
+
+        let response = Alphanumeric.sample_string(&mut thread_rng(), VERIFICATION_CODE_LEN);
+
+        let message = mail::verification_code(address, &response, VERIFICATION_CODE_EXPIRATION_MIN)
+            .or_else(|err| Err(AccountError::Other(format!("Couldn't send mail: {err}"))))?;
+        app_state
+            .mailer
+            .send(message)
+            .await
+            .or_else(|err| Err(AccountError::Other(format!("Couldn't send mail: {err}"))))?;
+
+        let key = key::email_verification(&account_id);
+        let tx = app_state.valkey_pool.multi();
+        // Start over, rather than carrying failed attempts over from an earlier address.
+        let _: () = tx.del(&key).await?;
+        let _: () = tx
+            .hset(
+                &key,
+                [
+                    ("email", email.as_str()),
+                    ("response", &response),
+                    ("primary", if primary { "true" } else { "false" }),
+                ],
+            )
+            .await?;
+        let _: () = tx
+            .expire(&key, VERIFICATION_CODE_EXPIRATION_MIN * 60, None)
+            .await?;
+        let _: () = tx.exec(false).await?;
+
+        Ok(())
+    }
+
+    /// Check the verification code for the address waiting to be added, and add it as a secondary
+    /// or primary email if correct.
+    pub async fn finish_verification(response: String) -> Result<(), AccountError> {
+        let expired = || {
+            AccountError::Other(String::from(
+                "Your verification code expired. Send a new one.",
+            ))
+        };
+
+        let request: HttpRequest = extract().await?;
+        let app_state = use_app_state()?;
+        let session = app_state.require_session(request).await?;
+
+        let key = key::email_verification(&encode_uuid(session.account_id));
+        let correct_data = app_state
+            .valkey_pool
+            .hgetall::<Option<HashMap<String, String>>, _>(&key)
+            .await?
+            .ok_or_else(expired)?;
+        let (Some(email), Some(correct_response)) =
+            (correct_data.get("email"), correct_data.get("response"))
+        else {
+            return Err(expired());
+        };
+
+        // Use up an attempt before comparing, so guesses made at the same time can't all be
+        // checked against the same count.
+        let tx = app_state.valkey_pool.multi();
+        let _: () = tx.hincrby(&key, "fails", 1).await?;
+        // In case the code expired in the meantime and the hincrby recreated it.
+        let _: () = tx
+            .expire(
+                &key,
+                VERIFICATION_CODE_EXPIRATION_MIN * 60,
+                Some(fred::types::ExpireOptions::NX),
+            )
+            .await?;
+        let (attempts, _): (i64, i64) = tx.exec(true).await?;
+        if attempts > MAX_VERIFICATION_ATTEMPTS {
+            app_state.valkey_pool.del::<(), _>(&key).await?;
+            return Err(expired());
+        }
+
+        if response != *correct_response {
+            let attempts_left = MAX_VERIFICATION_ATTEMPTS - attempts;
+            if attempts_left <= 0 {
+                app_state.valkey_pool.del::<(), _>(&key).await?;
+                return Err(AccountError::Other(String::from(
+                    "Wrong verification code. It was entered wrong too many times, so send a new one.",
+                )));
+            }
+            return Err(AccountError::Other(format!(
+                "Wrong verification code. {attempts_left} attempts left."
+            )));
+        }
+
+        // One-time code; if it's already gone, it was used in the meantime.
+        if app_state.valkey_pool.del::<i64, _>(&key).await? <= 0 {
+            return Err(expired());
+        }
+
+        if correct_data
+            .get("primary")
+            .is_some_and(|primary| primary == "true")
+        {
+            return change_primary_email(&app_state, session.account_id, email, false).await;
+        }
+
+        add_secondary_email(&app_state, session.account_id, email).await
+    }
+
+    /// Add a verified secondary email to an account, and tell the primary address how to undo it,
+    /// since the new address can be used to log in just like the primary one.
+    async fn add_secondary_email(
+        app_state: &AppState,
+        account_id: Uuid,
+        new_email: &str,
+    ) -> Result<(), AccountError> {
+        let mut transaction = app_state.db_pool.begin().await.or_else(|err| {
+            Err(AccountError::from_db(
+                err,
+                "Failed to create transaction for adding email",
+            ))
+        })?;
+
+        let (primary_email, secondary_emails) = sqlx::query_as::<_, (String, Vec<String>)>(
+            r#"
+            select email::text, coalesce(secondary_email, '{}')::text[]
+            from account
+            where id = $1
+            for update
+            "#,
+        )
+        .bind(account_id)
+        .fetch_one(&mut *transaction)
+        .await
+        .or_else(|err| Err(AccountError::from_db(err, "Couldn't get account from DB")))?;
+
+        transaction
+            .execute(
+                sqlx::query(
+                    r#"
+                    update account
+                    set secondary_email = array_append(coalesce(secondary_email, '{}'), $2::email)
+                    where id = $1
+                    "#,
+                )
+                .bind(account_id)
+                .bind(new_email),
+            )
+            .await
+            .or_else(|err| Err(AccountError::from_db(err, "Failed to add email")))?;
+
+        let link = save_email_change_undo(
+            app_state,
+            account_id,
+            &primary_email,
+            new_email,
+            &secondary_emails,
+        )
+        .await?;
+
+        transaction
+            .commit()
+            .await
+            .or_else(|err| Err(AccountError::from_db(err, "Failed to commit adding email")))?;
+
+        // Tell the primary address only once the account row is unlocked, since sending mail can
+        // be slow.
+        let address = primary_email
+            .parse::<lettre::address::Address>()
+            .or_else(|_| Err(AccountError::EmailInvalid))?;
+        let message =
+            mail::secondary_email_added(address, new_email, &link, EMAIL_CHANGE_UNDO_DAYS)
+                .or_else(|err| Err(AccountError::Other(format!("Couldn't send mail: {err}"))))?;
+        app_state
+            .mailer
+            .send(message)
+            .await
+            .or_else(|err| Err(AccountError::Other(format!("Couldn't send mail: {err}"))))?;
+
+        Ok(())
+    }
+
+    /// Save what's needed to undo a change to an account's emails, returning the link that undoes
+    /// it. Undoing puts back the primary email and the secondary emails from before the change, so
+    /// any emails added since, e.g. by whoever took over the account, are removed too. So are any
+    /// other ways to log in added since.
+    async fn save_email_change_undo(
+        app_state: &AppState,
+        account_id: Uuid,
+        old_email: &str,
+        new_email: &str,
+        secondary_emails: &[String],
+    ) -> Result<String, AccountError> {
+        let secondary_emails = serde_json::to_string(secondary_emails).or_else(|err| {
+            Err(AccountError::Other(format!(
+                "Failed to save secondary emails: {err}"
+            )))
+        })?;
+
+        let token = Alphanumeric.sample_string(&mut thread_rng(), UNDO_TOKEN_LEN);
+        let key = key::email_change_undo(&token);
+        let tx = app_state.valkey_pool.multi();
+        let _: () = tx
+            .hset(
+                &key,
+                [
+                    ("acctid", encode_uuid(account_id).as_str()),
+                    ("old", old_email),
+                    ("new", new_email),
+                    ("secondary", &secondary_emails),
+                    ("changed", &unix_time().to_string()),
+                ],
+            )
+            .await?;
+        let _: () = tx
+            .expire(&key, EMAIL_CHANGE_UNDO_DAYS * 24 * 60 * 60, None)
+            .await?;
+        let _: () = tx.exec(false).await?;
+
+        Ok(format!(
+            "{}/auth/undo-email-change?token={token}",
+            app_state.site_url
+        ))
+    }
+
+    /// Replace the primary email of an account, and tell the old address how to undo it, so that a
+    /// hijacked session can't quietly take over the account. The new email must already be
+    /// verified.
+    pub async fn change_primary_email(
+        app_state: &AppState,
+        account_id: Uuid,
+        new_email: &str,
+        keep_old_email: bool,
+    ) -> Result<(), AccountError> {
+        let mut transaction = app_state.db_pool.begin().await.or_else(|err| {
+            Err(AccountError::from_db(
+                err,
+                "Failed to create transaction for email change",
+            ))
+        })?;
+
+        let (old_email, secondary_emails) = sqlx::query_as::<_, (String, Vec<String>)>(
+            r#"
+            select email::text, coalesce(secondary_email, '{}')::text[]
+            from account
+            where id = $1
+            for update
+            "#,
+        )
+        .bind(account_id)
+        .fetch_one(&mut *transaction)
+        .await
+        .or_else(|err| Err(AccountError::from_db(err, "Couldn't get account from DB")))?;
+
+        transaction
+            .execute(
+                sqlx::query(
+                    r#"
+                    update account
+                    set
+                      email = $2::email,
+                      secondary_email = case
+                        when $3 then array_append(array_remove(secondary_email, $2::email), email)
+                        else array_remove(secondary_email, $2::email)
+                      end
+                    where id = $1
+                    "#,
+                )
+                .bind(account_id)
+                .bind(new_email)
+                .bind(keep_old_email),
+            )
+            .await
+            .or_else(|err| Err(AccountError::from_db(err, "Failed to change primary email")))?;
+
+        let link = save_email_change_undo(
+            app_state,
+            account_id,
+            &old_email,
+            new_email,
+            &secondary_emails,
+        )
+        .await?;
+
+        transaction
+            .commit()
+            .await
+            .or_else(|err| Err(AccountError::from_db(err, "Failed to commit email change")))?;
+
+        // Tell the old address only once the account row is unlocked, since sending mail can be
+        // slow.
+        let address = old_email
+            .parse::<lettre::address::Address>()
+            .or_else(|_| Err(AccountError::EmailInvalid))?;
+        let message =
+            mail::primary_email_changed(address, new_email, &link, EMAIL_CHANGE_UNDO_DAYS)
+                .or_else(|err| Err(AccountError::Other(format!("Couldn't send mail: {err}"))))?;
+        app_state
+            .mailer
+            .send(message)
+            .await
+            .or_else(|err| Err(AccountError::Other(format!("Couldn't send mail: {err}"))))?;
+
+        Ok(())
+    }
+
+    /// Swap a secondary email with the primary email of the logged in account.
+    pub async fn promote_secondary_email(email: String) -> Result<(), AccountError> {
+        let request: HttpRequest = extract().await?;
+        let app_state = use_app_state()?;
+        let session = app_state.require_session(request).await?;
+
+        let (is_secondary,) = sqlx::query_as::<_, (bool,)>(
+            r#"
+            select $2::email = any(coalesce(secondary_email, '{}'))
+            from account
+            where id = $1
+            "#,
+        )
+        .bind(session.account_id)
+        .bind(&email)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .or_else(|err| Err(AccountError::from_db(err, "Couldn't get account from DB")))?;
+        if !is_secondary {
+            return Err(AccountError::Other(String::from(
+                "That isn't one of your account's secondary emails.",
+            )));
+        }
+
+        // The old primary email becomes a secondary one, so nothing is lost.
+        change_primary_email(&app_state, session.account_id, &email, true).await
+    }
+
+    /// Make sure nothing is left of an unfinished verification.
+    pub async fn clear_verification(
+        app_state: &AppState,
+        session: &SessionInfo,
+    ) -> Result<(), ServerFnError> {
+        app_state
+            .valkey_pool
+            .del::<(), _>(key::email_verification(&encode_uuid(session.account_id)))
+            .await?;
+        Ok(())
+    }
+}
+
+const VERIFICATION_CODE_EXPIRATION_MIN: i64 = 20;
+const VERIFICATION_CODE_LEN: usize = 8;
+/// Wrong answers allowed before a verification code is invalidated.
+const MAX_VERIFICATION_ATTEMPTS: i64 = 5;
+
+/// Route definitions for email management.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("emails") view=EmailSettings /> }.into_inner()
+}
+
+/// An email waiting for its verification code to be entered.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PendingEmail {
+    pub email: String,
+    /// Whether it will replace the primary email, rather than being added as a secondary one.
+    pub primary: bool,
+}
+
+/// The emails of an account, as seen by its owner.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AccountEmails {
+    pub primary: String,
+    pub secondary: Vec<String>,
+    pub pending: Option<PendingEmail>,
+}
+
+/// Get the emails of the logged in account.
+#[server(client = CsrfClient)]
+async fn get_emails() -> Result<AccountEmails, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let (primary, secondary) = sqlx::query_as::<_, (String, Vec<String>)>(
+        r#"
+        select email::text, coalesce(secondary_email, '{}')::text[]
+        from account
+        where id = $1
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_one(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get account from DB: {err}"
+        )))
+    })?;
+
+    let [email, pending_primary] = app_state
+        .valkey_pool
+        .hmget::<[Option<String>; 2], _, _>(
+            key::email_verification(&encode_uuid(session.account_id)),
+            ("email", "primary"),
+        )
+        .await?;
+
+    Ok(AccountEmails {
+        primary,
+        secondary,
+        pending: email.map(|email| PendingEmail {
+            email,
+            primary: pending_primary.is_some_and(|primary| primary == "true"),
+        }),
+    })
+}
+
+/// Start adding a secondary email to the logged in account, by emailing it a verification code.
+#[server(client = CsrfClient)]
+async fn start_adding_email(email: String) -> Result<(), ServerFnError<AccountError>> {
+    use self::ssr::*;
+
+    Ok(send_verification_code(email, false).await?)
+}
+
+/// Start replacing the primary email of the logged in account with a new one, by emailing the new
+/// one a verification code.
+#[server(client = CsrfClient)]
+async fn start_changing_primary_email(email: String) -> Result<(), ServerFnError<AccountError>> {
+    use self::ssr::*;
+
+    Ok(send_verification_code(email, true).await?)
+}
+
+/// Finish adding an email by entering its verification code.
+#[server(client = CsrfClient)]
+async fn confirm_email(response: String) -> Result<(), ServerFnError<AccountError>> {
+    use self::ssr::*;
+
+    Ok(finish_verification(response).await?)
+}
+
+/// Give up on adding the email waiting for verification.
+#[server(client = CsrfClient)]
+async fn cancel_adding_email() -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    clear_verification(&app_state, &session).await
+}
+
+/// Remove a secondary email from the logged in account, so it can no longer be used to log in.
+#[server(client = CsrfClient)]
+async fn remove_email(email: String) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    if sqlx::query(
+        r#"
+        update account
+        set secondary_email = array_remove(secondary_email, $2::email)
+        where id = $1
+          and secondary_email @> array[$2]::email[]
+        "#,
+    )
+    .bind(session.account_id)
+    .bind(&email)
+    .execute(&app_state.db_pool)
+    .await
+    .or_else(|err| Err(ServerFnError::new(format!("Failed to remove email: {err}"))))?
+    .rows_affected()
+        == 0
+    {
+        return Err(ServerFnError::new(
+            "That isn't one of your account's secondary emails.",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Swap a secondary email with the primary email of the logged in account.
+#[server(client = CsrfClient)]
+async fn make_primary_email(email: String) -> Result<(), ServerFnError<AccountError>> {
+    use self::ssr::*;
+
+    Ok(promote_secondary_email(email).await?)
+}
+
+/// A single secondary email, with actions for it.
+#[component]
+fn SecondaryEmailEntry(
+    email: String,
+    remove_email: ServerAction<RemoveEmail>,
+    make_primary_email: ServerAction<MakePrimaryEmail>,
+) -> impl IntoView {
+    // Each form takes its own copy, since their children are moved into closures.
+    let primary_email = email.clone();
+    let remove_email_value = email.clone();
+
+    view! {
+        <li class="flex gap-2 py-1">
+            <span>{email}</span>
+            <CsrfActionForm action=make_primary_email>
+                <input type="hidden" name="email" value=primary_email />
+                <input
+                    type="submit"
+                    value="Make primary"
+                    class="py-0.5 px-2 bg-slate-200 hover:bg-slate-400"
+                />
+            </CsrfActionForm>
+            <CsrfActionForm action=remove_email>
+                <input type="hidden" name="email" value=remove_email_value />
+                <input
+                    type="submit"
+                    value="Remove"
+                    class="py-0.5 px-2 bg-red-200 hover:bg-red-400"
+                />
+            </CsrfActionForm>
+        </li>
+    }
+}
+
+/// Page for managing the current account's emails.
+#[component]
+pub fn EmailSettings() -> impl IntoView {
+    let start_adding_email = ServerAction::<StartAddingEmail>::new();
+    let start_changing_primary_email = ServerAction::<StartChangingPrimaryEmail>::new();
+    let confirm_email = ServerAction::<ConfirmEmail>::new();
+    let cancel_adding_email = ServerAction::<CancelAddingEmail>::new();
+    let remove_email = ServerAction::<RemoveEmail>::new();
+    let make_primary_email = ServerAction::<MakePrimaryEmail>::new();
+
+    let emails = Resource::new(
+        move || {
+            (
+                start_adding_email.version().get(),
+                start_changing_primary_email.version().get(),
+                confirm_email.version().get(),
+                cancel_adding_email.version().get(),
+                remove_email.version().get(),
+                make_primary_email.version().get(),
+            )
+        },
+        |_| get_emails(),
+    );
+
+    view! {
+        <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
+            <legend class="m-2 text-2xl font-bold">Emails</legend>
+            <p>
+                "You can log in with any of these emails. Account related mail goes to the primary email."
+            </p>
+            <ShowActionStatus action=remove_email success="Email removed." />
+            <ShowFormStatus action=make_primary_email success="Primary email changed." />
+            <Transition fallback=move || {
+                view! { <Spinner /> }
+            }>
+                {move || Suspend::new(async move {
+                    match emails.await {
+                        Ok(emails) => {
+                            view! {
+                                <p class="py-2">
+                                    <span class="font-bold">"Primary: "</span>
+                                    {emails.primary}
+                                </p>
+                                <ul>
+                                    {emails
+                                        .secondary
+                                        .into_iter()
+                                        .map(|email| {
+                                            view! {
+                                                <SecondaryEmailEntry
+                                                    email=email
+                                                    remove_email=remove_email
+                                                    make_primary_email=make_primary_email
+                                                />
+                                            }
+                                        })
+                                        .collect_view()}
+                                </ul>
+                                {match emails.pending {
+                                    Some(pending) => {
+                                        view! {
+                                            <CsrfActionForm action=confirm_email>
+                                                <p>
+                                                    "A verification code was sent to " {pending.email}
+                                                    ". Enter it within " {VERIFICATION_CODE_EXPIRATION_MIN}
+                                                    {if pending.primary {
+                                                        " minutes to make it your primary email. Your current primary email will be told how to undo the change. After "
+                                                    } else {
+                                                        " minutes to add the email. After "
+                                                    }} {MAX_VERIFICATION_ATTEMPTS}
+                                                    " wrong attempts, you'll need a new code."
+                                                </p>
+                                                <div class="flex gap-2 py-2">
+                                                    <label for="response">"Verification code:"</label>
+                                                    <input
+                                                        type="text"
+                                                        name="response"
+                                                        id="response"
+                                                        class="px-1 h-full bg-gray-200 border border-gray-500 invalid:border-red-500"
+                                                        minlength=VERIFICATION_CODE_LEN
+                                                        maxlength=VERIFICATION_CODE_LEN
+                                                        pattern="^[A-Za-z0-9]*$"
+                                                        required
+                                                        autocomplete="off"
+                                                    />
+                                                    <input
+                                                        type="submit"
+                                                        value="Add email"
+                                                        class="px-2 h-full bg-green-200 hover:bg-green-300"
+                                                    />
+                                                </div>
+                                            </CsrfActionForm>
+                                            <CsrfActionForm action=cancel_adding_email>
+                                                <input
+                                                    type="submit"
+                                                    value="Cancel"
+                                                    class="py-0.5 px-2 bg-slate-200 hover:bg-slate-400"
+                                                />
+                                            </CsrfActionForm>
+                                        }
+                                            .into_any()
+                                    }
+                                    None => {
+                                        view! {
+                                            <CsrfActionForm action=start_adding_email>
+                                                <div class="flex gap-2 py-2">
+                                                    <label for="new_email">"Add email:"</label>
+                                                    <input
+                                                        type="email"
+                                                        name="email"
+                                                        id="new_email"
+                                                        placeholder="email"
+                                                        class="px-1 h-full bg-gray-200 border border-gray-500 invalid:border-red-500"
+                                                        required
+                                                    />
+                                                    <input
+                                                        type="submit"
+                                                        value="Send verification code"
+                                                        class="px-2 h-full bg-green-200 hover:bg-green-300"
+                                                    />
+                                                </div>
+                                            </CsrfActionForm>
+                                            <CsrfActionForm action=start_changing_primary_email>
+                                                <div class="flex gap-2 py-2">
+                                                    <label for="new_primary_email">
+                                                        "Change primary email:"
+                                                    </label>
+                                                    <input
+                                                        type="email"
+                                                        name="email"
+                                                        id="new_primary_email"
+                                                        placeholder="email"
+                                                        class="px-1 h-full bg-gray-200 border border-gray-500 invalid:border-red-500"
+                                                        required
+                                                    />
+                                                    <input
+                                                        type="submit"
+                                                        value="Send verification code"
+                                                        class="px-2 h-full bg-green-200 hover:bg-green-300"
+                                                    />
+                                                </div>
+                                            </CsrfActionForm>
+                                        }
+                                            .into_any()
+                                    }
+                                }}
+                            }
+                                .into_any()
+                        }
+                        Err(err) => view! { <ShowServerFnError error=err /> }.into_any(),
+                    }
+                })}
+            </Transition>
+            <ShowFieldError action=start_adding_email field="email" />
+            <ShowFormStatus action=start_adding_email />
+            <ShowFieldError action=start_changing_primary_email field="email" />
+            <ShowFormStatus action=start_changing_primary_email />
+            <ShowFieldError action=confirm_email field="email" />
+            <ShowFormStatus action=confirm_email success="Email verified." />
+            <ShowActionStatus action=cancel_adding_email />
+        </fieldset>
+    }
+}
@@ -0,0 +1,235 @@
+/// Management of the identities at OpenID Connect providers an account can log in with.
+use crate::components::auth::oidc::get_login_providers;
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos_router::components::*;
+use leptos_router::{MatchNestedRoutes, path};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::cookie::use_response_options;
+    pub use crate::ssr::oidc::start_oidc_flow;
+    pub use crate::ssr::uuid_codec::{decode_uuid, encode_uuid};
+
+    pub use actix_web::HttpRequest;
+    pub use leptos_actix::extract;
+    pub use uuid::Uuid;
+}
+
+/// Route definitions for linked login management.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("logins") view=LinkedLoginSettings /> }.into_inner()
+}
+
+/// A linked identity as shown to its owner.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LinkedIdentityEntry {
+    pub id: String,
+    pub provider: String,
+    pub email: Option<String>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+/// Get every identity linked to the logged in account.
+#[server(client = CsrfClient)]
+async fn get_linked_identities() -> Result<Vec<LinkedIdentityEntry>, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let identities = sqlx::query_as::<_, (Uuid, String, Option<String>, String, Option<String>)>(
+        r#"
+        select id, provider, email, created_at::date::text, last_used_at::date::text
+        from linked_identity
+        where account_id = $1
+        order by created_at
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get linked logins from DB: {err}"
+        )))
+    })?
+    .into_iter()
+    .map(
+        |(id, provider, email, created_at, last_used_at)| LinkedIdentityEntry {
+            id: encode_uuid(id),
+            // Providers can be removed from the config, leaving only the ID to show.
+            provider: app_state
+                .oidc
+                .provider(&provider)
+                .map(|provider| provider.name.clone())
+                .unwrap_or(provider),
+            email,
+            created_at,
+            last_used_at,
+        },
+    )
+    .collect();
+    Ok(identities)
+}
+
+/// Send the user to a provider to link their identity there to the logged in account.
+#[server(client = CsrfClient)]
+async fn start_oidc_link(provider: String) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+    let response_options = use_response_options()?;
+    start_oidc_flow(
+        &app_state,
+        &response_options,
+        &provider,
+        Some(session.account_id),
+    )
+    .await
+}
+
+/// Unlink an identity from the logged in account. Email login always works, so this can't lock
+/// anyone out.
+#[server(client = CsrfClient)]
+async fn unlink_identity(id: String) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+    let id = decode_uuid(&id).or_else(|_| Err(ServerFnError::new("That login isn't linked.")))?;
+
+    if sqlx::query(
+        r#"
+        delete from linked_identity
+        where id = $1
+          and account_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(session.account_id)
+    .execute(&app_state.db_pool)
+    .await
+    .or_else(|err| Err(ServerFnError::new(format!("Failed to unlink login: {err}"))))?
+    .rows_affected()
+        == 0
+    {
+        return Err(ServerFnError::new("That login isn't linked."));
+    }
+
+    Ok(())
+}
+
+/// Page listing the identities linked to the current account.
+#[component]
+pub fn LinkedLoginSettings() -> impl IntoView {
+    let start_oidc_link = ServerAction::<StartOidcLink>::new();
+    let unlink_identity = ServerAction::<UnlinkIdentity>::new();
+    let identities = Resource::new(
+        move || unlink_identity.version().get(),
+        |_| get_linked_identities(),
+    );
+    let providers = OnceResource::new(get_login_providers());
+
+    view! {
+        <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
+            <legend class="m-2 text-2xl font-bold">"Linked logins"</legend>
+            <p>"Log in with your account at another site, once it's linked here."</p>
+            <ShowActionStatus action=unlink_identity success="Login unlinked." />
+            <Transition fallback=move || {
+                view! { <Spinner /> }
+            }>
+                {move || Suspend::new(async move {
+                    match identities.await {
+                        Ok(identities) if identities.is_empty() => {
+                            view! { <p class="my-2">"You haven't linked any logins yet."</p> }
+                                .into_any()
+                        }
+                        Ok(identities) => {
+                            view! {
+                                <table class="my-2 table-auto">
+                                    <thead>
+                                        <tr class="text-left">
+                                            <th class="px-2">"Provider"</th>
+                                            <th class="px-2">"Email"</th>
+                                            <th class="px-2">"Linked"</th>
+                                            <th class="px-2">"Last used"</th>
+                                            <th class="px-2"></th>
+                                        </tr>
+                                    </thead>
+                                    <tbody>
+                                        {identities
+                                            .into_iter()
+                                            .map(|identity| {
+                                                view! {
+                                                    <tr>
+                                                        <td class="px-2">{identity.provider}</td>
+                                                        <td class="px-2">{identity.email}</td>
+                                                        <td class="px-2">{identity.created_at}</td>
+                                                        <td class="px-2">
+                                                            {identity
+                                                                .last_used_at
+                                                                .unwrap_or_else(|| String::from("never"))}
+                                                        </td>
+                                                        <td class="px-2">
+                                                            <CsrfActionForm action=unlink_identity>
+                                                                <input type="hidden" name="id" value=identity.id />
+                                                                <input
+                                                                    type="submit"
+                                                                    value="Unlink"
+                                                                    class="py-0.5 px-2 bg-red-200 hover:bg-red-400"
+                                                                />
+                                                            </CsrfActionForm>
+                                                        </td>
+                                                    </tr>
+                                                }
+                                            })
+                                            .collect_view()}
+                                    </tbody>
+                                </table>
+                            }
+                                .into_any()
+                        }
+                        Err(err) => view! { <ShowServerFnError error=err /> }.into_any(),
+                    }
+                })}
+            </Transition>
+            <Transition>
+                {move || Suspend::new(async move {
+                    match providers.await {
+                        Ok(providers) => {
+                            providers
+                                .into_iter()
+                                .map(|provider| {
+                                    view! {
+                                        <CsrfActionForm action=start_oidc_link>
+                                            <input type="hidden" name="provider" value=provider.id />
+                                            <input
+                                                type="submit"
+                                                value=format!("Link {}", provider.name)
+                                                class="py-0.5 px-2 my-1 font-bold bg-green-200 hover:bg-green-400"
+                                            />
+                                        </CsrfActionForm>
+                                    }
+                                })
+                                .collect_view()
+                                .into_any()
+                        }
+                        Err(err) => view! { <ShowServerFnError error=err /> }.into_any(),
+                    }
+                })}
+            </Transition>
+            <ShowActionStatus action=start_oidc_link />
+        </fieldset>
+    }
+}
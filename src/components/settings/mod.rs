@@ -0,0 +1,72 @@
+/// Account and profile settings.
+use crate::components::session::RequireLogin;
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos_router::components::*;
+use leptos_router::*;
+
+mod account;
+mod emails;
+mod logins;
+mod passkeys;
+mod profiles;
+mod sessions;
+mod tokens;
+mod two_factor;
+
+/// Visual wrapper around all settings views, with links between the sections.
+#[component]
+fn SettingsWrapper() -> impl IntoView {
+    view! {
+        <h1 class="mb-2 text-4xl font-bold">"Settings"</h1>
+        <nav class="flex gap-2 mb-2">
+            <div>
+                <ANorm href="/settings/profiles">"Profiles"</ANorm>
+            </div>
+            <div>
+                <ANorm href="/settings/emails">"Emails"</ANorm>
+            </div>
+            <div>
+                <ANorm href="/settings/sessions">"Sessions"</ANorm>
+            </div>
+            <div>
+                <ANorm href="/settings/passkeys">"Passkeys"</ANorm>
+            </div>
+            <div>
+                <ANorm href="/settings/two-factor">"Two-factor"</ANorm>
+            </div>
+            <div>
+                <ANorm href="/settings/logins">"Linked logins"</ANorm>
+            </div>
+            <div>
+                <ANorm href="/settings/tokens">"API tokens"</ANorm>
+            </div>
+            <div>
+                <ANorm href="/settings/account">"Account"</ANorm>
+            </div>
+        </nav>
+        <RequireLogin>
+            <Outlet />
+        </RequireLogin>
+    }
+}
+
+/// Route definitions for /settings subtree.
+#[component(transparent)]
+pub fn SettingsRoutes() -> impl MatchNestedRoutes + Clone {
+    view! {
+        <ParentRoute path=path!("settings") view=SettingsWrapper>
+            <Route path=path!("") view=|| view! { <Redirect path="/settings/profiles" /> } />
+            <profiles::Routes />
+            <emails::Routes />
+            <sessions::Routes />
+            <passkeys::Routes />
+            <two_factor::Routes />
+            <logins::Routes />
+            <tokens::Routes />
+            <account::Routes />
+        </ParentRoute>
+    }
+    .into_inner()
+}
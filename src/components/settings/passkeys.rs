@@ -0,0 +1,358 @@
+/// Management of the passkeys an account can log in with.
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::passkey::create_credential;
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos::server_fn::codec::Json;
+use leptos_router::components::*;
+use leptos_router::{MatchNestedRoutes, path};
+use serde::{Deserialize, Serialize};
+use webauthn_rs_proto::{CreationChallengeResponse, RegisterPublicKeyCredential};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::key;
+    pub use crate::ssr::uuid_codec::{decode_uuid, encode_uuid};
+
+    pub use actix_web::HttpRequest;
+    pub use fred::prelude::KeysInterface;
+    pub use leptos_actix::extract;
+    pub use uuid::Uuid;
+    pub use webauthn_rs::prelude::{CredentialID, PasskeyRegistration};
+    pub use webauthn_rs_proto::ResidentKeyRequirement;
+
+    /// How long the browser has to create a passkey after registration starts.
+    pub const PASSKEY_REGISTRATION_TIMEOUT_SEC: i64 = 5 * 60;
+}
+
+/// Route definitions for passkey management.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("passkeys") view=PasskeySettings /> }.into_inner()
+}
+
+/// A passkey as shown to its owner.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PasskeyEntry {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+/// Get every passkey of the logged in account.
+#[server(client = CsrfClient)]
+async fn get_passkeys() -> Result<Vec<PasskeyEntry>, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    Ok(sqlx::query_as::<_, (Uuid, String, String, Option<String>)>(
+        r#"
+            select id, name, created_at::date::text, last_used_at::date::text
+            from webauthn_credential
+            where account_id = $1
+            order by created_at
+            "#,
+    )
+    .bind(session.account_id)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get passkeys from DB: {err}"
+        )))
+    })?
+    .into_iter()
+    .map(|(id, name, created_at, last_used_at)| PasskeyEntry {
+        id: encode_uuid(id),
+        name,
+        created_at,
+        last_used_at,
+    })
+    .collect())
+}
+
+/// Start adding a passkey to the logged in account, returning the options for the browser to
+/// create it with.
+#[server(client = CsrfClient)]
+async fn start_passkey_registration() -> Result<CreationChallengeResponse, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let (email,) = sqlx::query_as::<_, (String,)>(
+        r#"
+        select email::text
+        from account
+        where id = $1
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_one(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get account from DB: {err}"
+        )))
+    })?;
+
+    // The same authenticator shouldn't be added twice.
+    let existing: Vec<CredentialID> = sqlx::query_as::<_, (Vec<u8>,)>(
+        r#"
+        select credential_id
+        from webauthn_credential
+        where account_id = $1
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get passkeys from DB: {err}"
+        )))
+    })?
+    .into_iter()
+    .map(|(credential_id,)| credential_id.into())
+    .collect();
+
+    let (mut options, registration) = app_state
+        .webauthn
+        .start_passkey_registration(session.account_id, &email, &email, Some(existing))
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Failed to start adding passkey: {err}"
+            )))
+        })?;
+
+    // Logging in doesn't ask for an email first, so the authenticator has to be able to find the
+    // passkey by itself.
+    if let Some(selection) = options.public_key.authenticator_selection.as_mut() {
+        selection.resident_key = Some(ResidentKeyRequirement::Required);
+        selection.require_resident_key = true;
+    }
+
+    let registration = serde_json::to_string(&registration).or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to save passkey registration: {err}"
+        )))
+    })?;
+    app_state
+        .valkey_pool
+        .set::<(), _, _>(
+            key::passkey_registration(&encode_uuid(session.account_id)),
+            registration,
+            Some(fred::types::Expiration::EX(
+                PASSKEY_REGISTRATION_TIMEOUT_SEC,
+            )),
+            None,
+            false,
+        )
+        .await?;
+
+    Ok(options)
+}
+
+/// Finish adding a passkey to the logged in account, with the credential the browser created.
+#[server(input = Json, client = CsrfClient)]
+async fn finish_passkey_registration(
+    name: String,
+    credential: RegisterPublicKeyCredential,
+) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let registration = app_state
+        .valkey_pool
+        .getdel::<Option<String>, _>(key::passkey_registration(&encode_uuid(session.account_id)))
+        .await?
+        .ok_or_else(|| ServerFnError::new("Adding the passkey took too long. Try again."))?;
+    let registration: PasskeyRegistration = serde_json::from_str(&registration).or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to read passkey registration: {err}"
+        )))
+    })?;
+
+    let passkey = app_state
+        .webauthn
+        .finish_passkey_registration(&credential, &registration)
+        .or_else(|err| Err(ServerFnError::new(format!("Couldn't add passkey: {err}"))))?;
+    let serialized = serde_json::to_string(&passkey)
+        .or_else(|err| Err(ServerFnError::new(format!("Failed to save passkey: {err}"))))?;
+
+    // Names are only for telling passkeys apart, so a default or a shortened one is fine.
+    let name: String = name.trim().chars().take(50).collect();
+    sqlx::query(
+        r#"
+        insert into webauthn_credential (account_id, credential_id, passkey, name)
+        values ($1, $2, $3::jsonb, $4)
+        "#,
+    )
+    .bind(session.account_id)
+    .bind(passkey.cred_id().as_ref())
+    .bind(serialized)
+    .bind(if name.is_empty() { "Passkey" } else { &name })
+    .execute(&app_state.db_pool)
+    .await
+    .or_else(|err| Err(ServerFnError::new(format!("Failed to save passkey: {err}"))))?;
+
+    Ok(())
+}
+
+/// Remove one of the logged in account's passkeys.
+#[server(client = CsrfClient)]
+async fn remove_passkey(id: String) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+    let id =
+        decode_uuid(&id).or_else(|_| Err(ServerFnError::new("That passkey doesn't exist.")))?;
+
+    if sqlx::query(
+        r#"
+        delete from webauthn_credential
+        where id = $1
+          and account_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(session.account_id)
+    .execute(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to remove passkey: {err}"
+        )))
+    })?
+    .rows_affected()
+        == 0
+    {
+        return Err(ServerFnError::new("That passkey doesn't exist."));
+    }
+
+    Ok(())
+}
+
+/// Run the whole ceremony of adding a passkey: get options from the server, have the browser
+/// create the passkey, and send it back.
+async fn add_passkey(name: String) -> Result<(), ServerFnError> {
+    let options = start_passkey_registration().await?;
+    let credential = create_credential(options).await?;
+    finish_passkey_registration(name, credential).await
+}
+
+/// Page listing the current account's passkeys.
+#[component]
+pub fn PasskeySettings() -> impl IntoView {
+    let add_passkey = Action::new_unsync(|name: &String| add_passkey(name.clone()));
+    let remove_passkey = ServerAction::<RemovePasskey>::new();
+    let passkeys = Resource::new(
+        move || (add_passkey.version().get(), remove_passkey.version().get()),
+        |_| get_passkeys(),
+    );
+    let name = RwSignal::new(String::new());
+
+    view! {
+        <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
+            <legend class="m-2 text-2xl font-bold">"Passkeys"</legend>
+            <p>
+                "Passkeys let you log in with your device's screen lock or a security key, without waiting for an email."
+            </p>
+            <ShowActionStatus action=remove_passkey success="Passkey removed." />
+            <Transition fallback=move || {
+                view! { <Spinner /> }
+            }>
+                {move || Suspend::new(async move {
+                    match passkeys.await {
+                        Ok(passkeys) if passkeys.is_empty() => {
+                            view! { <p class="my-2">"You haven't added any passkeys yet."</p> }
+                                .into_any()
+                        }
+                        Ok(passkeys) => {
+                            view! {
+                                <table class="my-2 table-auto">
+                                    <thead>
+                                        <tr class="text-left">
+                                            <th class="px-2">"Name"</th>
+                                            <th class="px-2">"Added"</th>
+                                            <th class="px-2">"Last used"</th>
+                                            <th class="px-2"></th>
+                                        </tr>
+                                    </thead>
+                                    <tbody>
+                                        {passkeys
+                                            .into_iter()
+                                            .map(|passkey| {
+                                                view! {
+                                                    <tr>
+                                                        <td class="px-2">{passkey.name}</td>
+                                                        <td class="px-2">{passkey.created_at}</td>
+                                                        <td class="px-2">
+                                                            {passkey
+                                                                .last_used_at
+                                                                .unwrap_or_else(|| String::from("never"))}
+                                                        </td>
+                                                        <td class="px-2">
+                                                            <CsrfActionForm action=remove_passkey>
+                                                                <input type="hidden" name="id" value=passkey.id />
+                                                                <input
+                                                                    type="submit"
+                                                                    value="Remove"
+                                                                    class="py-0.5 px-2 bg-red-200 hover:bg-red-400"
+                                                                />
+                                                            </CsrfActionForm>
+                                                        </td>
+                                                    </tr>
+                                                }
+                                            })
+                                            .collect_view()}
+                                    </tbody>
+                                </table>
+                            }
+                                .into_any()
+                        }
+                        Err(err) => view! { <ShowServerFnError error=err /> }.into_any(),
+                    }
+                })}
+            </Transition>
+            <form on:submit=move |ev| {
+                ev.prevent_default();
+                add_passkey.dispatch(name.get());
+            }>
+                <fieldset class="p-2 my-2 border-2 border-slate-500">
+                    <legend class="text-xl font-bold">"New passkey"</legend>
+                    <div class="py-2">
+                        <label for="passkey_name">"Name: "</label>
+                        <input
+                            type="text"
+                            id="passkey_name"
+                            placeholder="e.g. My phone"
+                            maxlength="50"
+                            autocomplete="off"
+                            class="p-0.5 border-2 border-slate-300"
+                            bind:value=name
+                        />
+                    </div>
+                    <input
+                        type="submit"
+                        value="Add passkey"
+                        class="py-0.5 px-2 font-bold bg-green-200 hover:bg-green-400"
+                    />
+                    <ShowClientActionStatus action=add_passkey success="Passkey added." />
+                </fieldset>
+            </form>
+        </fieldset>
+    }
+}
@@ -0,0 +1,807 @@
+/// Management of the profiles owned by an account.
+use crate::components::account_error::*;
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::ui::*;
+use crate::components::username::*;
+
+use leptos::prelude::*;
+use leptos_router::components::*;
+use leptos_router::{MatchNestedRoutes, path};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::components::account_error::AccountError;
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::username::check_username_allowed;
+
+    pub use actix_web::HttpRequest;
+    pub use leptos_actix::extract;
+    pub use sqlx::Executor;
+    pub use sqlx::Row;
+    pub use uuid::Uuid;
+
+    /// Optional text fields are stored as null rather than empty.
+    pub fn non_empty(value: Option<String>) -> Option<String> {
+        value.filter(|value| !value.is_empty())
+    }
+
+    /// Create a new profile for the logged in account.
+    pub async fn insert_profile(
+        username: String,
+        display_name: Option<String>,
+        bio: Option<String>,
+        make_default: Option<String>,
+    ) -> Result<(), AccountError> {
+        let request: HttpRequest = extract().await?;
+        let app_state = use_app_state()?;
+        let session = app_state.require_session(request).await?;
+
+        check_username_allowed(&app_state.db_pool, &username, Some(session.account_id)).await?;
+
+        // Create a transaction so that the default profile is only changed if the profile is created.
+        let mut transaction = app_state.db_pool.begin().await.or_else(|err| {
+            Err(AccountError::from_db(
+                err,
+                "Failed to create transaction for profile creation",
+            ))
+        })?;
+
+        let profile_id: Uuid = transaction
+            .fetch_one(
+                sqlx::query(
+                    r#"
+                    insert into profile (username, account_id, display_name, bio)
+                    values ($1, $2, $3, $4)
+                    returning id
+                    "#,
+                )
+                .bind(&username)
+                .bind(session.account_id)
+                .bind(non_empty(display_name))
+                .bind(non_empty(bio)),
+            )
+            .await
+            .or_else(|err| Err(AccountError::from_db(err, "Failed to create profile")))?
+            .get(0);
+
+        if make_default.is_some() {
+            transaction
+                .execute(
+                    sqlx::query(
+                        r#"
+                        update account
+                        set default_profile = $1
+                        where id = $2
+                        "#,
+                    )
+                    .bind(profile_id)
+                    .bind(session.account_id),
+                )
+                .await
+                .or_else(|err| Err(AccountError::from_db(err, "Failed to set default profile")))?;
+        }
+
+        transaction.commit().await.or_else(|err| {
+            Err(AccountError::from_db(
+                err,
+                "Failed to commit profile creation",
+            ))
+        })?;
+
+        Ok(())
+    }
+
+    /// Edit the display name and bio of one of the logged in account's profiles.
+    pub async fn update_profile(
+        username: String,
+        display_name: Option<String>,
+        bio: Option<String>,
+    ) -> Result<(), AccountError> {
+        let request: HttpRequest = extract().await?;
+        let app_state = use_app_state()?;
+        let session = app_state.require_session(request).await?;
+        let display_name = non_empty(display_name);
+
+        if sqlx::query(
+            r#"
+            update profile
+            set display_name = $3, bio = $4
+            where account_id = $1
+              and username = $2
+            "#,
+        )
+        .bind(session.account_id)
+        .bind(&username)
+        .bind(&display_name)
+        .bind(non_empty(bio))
+        .execute(&app_state.db_pool)
+        .await
+        .or_else(|err| Err(AccountError::from_db(err, "Failed to edit profile")))?
+        .rows_affected()
+            == 0
+        {
+            return Err(AccountError::Other(String::from(
+                "You don't have a profile by that name.",
+            )));
+        }
+
+        // Keep the display name shown for the current session up to date.
+        if session.username == username {
+            app_state
+                .set_session_profile(&session.session_id, Some(username), display_name)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// How many times a profile can be renamed within USERNAME_CHANGE_WINDOW_DAYS.
+    pub const USERNAME_CHANGES_PER_WINDOW: i64 = 2;
+    pub const USERNAME_CHANGE_WINDOW_DAYS: i32 = 30;
+
+    /// Change the username of one of the logged in account's profiles. The old username is kept in
+    /// the profile's history by the database.
+    pub async fn change_username(
+        username: String,
+        new_username: String,
+    ) -> Result<(), AccountError> {
+        let request: HttpRequest = extract().await?;
+        let app_state = use_app_state()?;
+        let session = app_state.require_session(request).await?;
+
+        if new_username == username {
+            return Ok(());
+        }
+
+        check_username_allowed(&app_state.db_pool, &new_username, Some(session.account_id)).await?;
+
+        let mut transaction = app_state.db_pool.begin().await.or_else(|err| {
+            Err(AccountError::from_db(
+                err,
+                "Failed to create transaction for username change",
+            ))
+        })?;
+
+        // Lock the profile, so concurrent renames can't all get in under the limit.
+        let Some((profile_id, recent_changes)) = sqlx::query_as::<_, (Uuid, i64)>(
+            r#"
+            select
+              id,
+              (
+                select count(*)
+                from profile_username_history
+                where profile_id = profile.id
+                  and changed_at > now() - make_interval(days => $3)
+              )
+            from profile
+            where account_id = $1
+              and username = $2
+            for update
+            "#,
+        )
+        .bind(session.account_id)
+        .bind(&username)
+        .bind(USERNAME_CHANGE_WINDOW_DAYS)
+        .fetch_optional(&mut *transaction)
+        .await
+        .or_else(|err| Err(AccountError::from_db(err, "Failed to get profile")))?
+        else {
+            return Err(AccountError::Other(String::from(
+                "You don't have a profile by that name.",
+            )));
+        };
+
+        if recent_changes >= USERNAME_CHANGES_PER_WINDOW {
+            return Err(AccountError::Other(format!(
+                "A profile's username can only be changed {USERNAME_CHANGES_PER_WINDOW} times every {USERNAME_CHANGE_WINDOW_DAYS} days."
+            )));
+        }
+
+        transaction
+            .execute(
+                sqlx::query(
+                    r#"
+                    update profile
+                    set username = $2
+                    where id = $1
+                    "#,
+                )
+                .bind(profile_id)
+                .bind(&new_username),
+            )
+            .await
+            .or_else(|err| Err(AccountError::from_db(err, "Failed to change username")))?;
+
+        transaction.commit().await.or_else(|err| {
+            Err(AccountError::from_db(
+                err,
+                "Failed to commit username change",
+            ))
+        })?;
+
+        app_state
+            .replace_session_profile(session.account_id, &username, Some(&new_username))
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Route definitions for profile management.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("profiles") view=ProfileSettings /> }.into_inner()
+}
+
+/// A profile as seen by its owner.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OwnProfile {
+    pub username: String,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    /// Whether this is the account's default profile.
+    pub default: bool,
+}
+
+/// Profile related settings of the current account.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OwnProfiles {
+    pub ask_for_profile_on_login: bool,
+    pub profiles: Vec<OwnProfile>,
+}
+
+/// Get the profiles of the logged in account, along with related settings.
+#[server(client = CsrfClient)]
+async fn get_profile_settings() -> Result<OwnProfiles, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let (ask_for_profile_on_login,) = sqlx::query_as::<_, (bool,)>(
+        r#"
+        select ask_for_profile_on_login
+        from account
+        where id = $1
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_one(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get account from DB: {err}"
+        )))
+    })?;
+
+    let profiles = sqlx::query_as::<_, (String, Option<String>, Option<String>, bool)>(
+        r#"
+        select
+          profile.username,
+          profile.display_name,
+          profile.bio,
+          account.default_profile is not distinct from profile.id
+        from
+          profile
+          join account on profile.account_id = account.id
+        where
+          account.id = $1
+        order by profile.created_at
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get profiles from DB: {err}"
+        )))
+    })?
+    .into_iter()
+    .map(|(username, display_name, bio, default)| OwnProfile {
+        username,
+        display_name,
+        bio,
+        default,
+    })
+    .collect();
+
+    Ok(OwnProfiles {
+        ask_for_profile_on_login,
+        profiles,
+    })
+}
+
+/// Create a new profile for the logged in account.
+#[server(client = CsrfClient)]
+async fn create_profile(
+    username: String,
+    display_name: Option<String>,
+    bio: Option<String>,
+    make_default: Option<String>,
+) -> Result<(), ServerFnError<AccountError>> {
+    use self::ssr::*;
+
+    Ok(insert_profile(username, display_name, bio, make_default).await?)
+}
+
+/// Edit the display name and bio of one of the logged in account's profiles.
+#[server(client = CsrfClient)]
+async fn edit_profile(
+    username: String,
+    display_name: Option<String>,
+    bio: Option<String>,
+) -> Result<(), ServerFnError<AccountError>> {
+    use self::ssr::*;
+
+    Ok(update_profile(username, display_name, bio).await?)
+}
+
+/// Change the username of one of the logged in account's profiles. Links using the old username
+/// keep working, and nobody else can take it for a while.
+#[server(client = CsrfClient)]
+async fn rename_profile(
+    old_username: String,
+    username: String,
+) -> Result<(), ServerFnError<AccountError>> {
+    use self::ssr::*;
+
+    Ok(change_username(old_username, username).await?)
+}
+
+/// Change which profile is used by default when logging in. An empty username makes reader mode the
+/// default.
+#[server(client = CsrfClient)]
+async fn set_default_profile(username: String) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let query = if username.is_empty() {
+        sqlx::query(
+            r#"
+            update account
+            set default_profile = null
+            where id = $1
+            "#,
+        )
+        .bind(session.account_id)
+    } else {
+        sqlx::query(
+            r#"
+            update account
+            set default_profile = profile.id
+            from profile
+            where account.id = $1
+              and profile.account_id = $1
+              and profile.username = $2
+            "#,
+        )
+        .bind(session.account_id)
+        .bind(&username)
+    };
+
+    if query
+        .execute(&app_state.db_pool)
+        .await
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Failed to set default profile: {err}"
+            )))
+        })?
+        .rows_affected()
+        == 0
+    {
+        return Err(ServerFnError::new("You don't have a profile by that name."));
+    }
+
+    Ok(())
+}
+
+/// Set whether to ask which profile to use every time the account logs in.
+#[server(client = CsrfClient)]
+async fn set_ask_for_profile_on_login(ask: Option<String>) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    sqlx::query(
+        r#"
+        update account
+        set ask_for_profile_on_login = $2
+        where id = $1
+        "#,
+    )
+    .bind(session.account_id)
+    .bind(ask.is_some())
+    .execute(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to update account: {err}"
+        )))
+    })?;
+
+    Ok(())
+}
+
+/// Delete one of the logged in account's profiles.
+#[server(client = CsrfClient)]
+async fn delete_profile(username: String) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let mut transaction = app_state.db_pool.begin().await.or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to create transaction for profile deletion: {err}"
+        )))
+    })?;
+
+    // The default profile has to be unset first, or the foreign key prevents deletion. Reader mode
+    // becomes the default.
+    transaction
+        .execute(
+            sqlx::query(
+                r#"
+                update account
+                set default_profile = null
+                from profile
+                where account.id = $1
+                  and account.default_profile = profile.id
+                  and profile.username = $2
+                "#,
+            )
+            .bind(session.account_id)
+            .bind(&username),
+        )
+        .await
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Failed to unset default profile: {err}"
+            )))
+        })?;
+
+    if transaction
+        .execute(
+            sqlx::query(
+                r#"
+                delete from profile
+                where account_id = $1
+                  and username = $2
+                "#,
+            )
+            .bind(session.account_id)
+            .bind(&username),
+        )
+        .await
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Failed to delete profile: {err}"
+            )))
+        })?
+        .rows_affected()
+        == 0
+    {
+        return Err(ServerFnError::new("You don't have a profile by that name."));
+    }
+
+    transaction.commit().await.or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to commit profile deletion: {err}"
+        )))
+    })?;
+
+    // No session can keep acting as a profile that no longer exists.
+    app_state
+        .replace_session_profile(session.account_id, &username, None)
+        .await?;
+
+    Ok(())
+}
+
+/// Settings for a single existing profile.
+#[component]
+fn ProfileEntry(
+    profile: OwnProfile,
+    edit_profile: ServerAction<EditProfile>,
+    rename_profile: ServerAction<RenameProfile>,
+    last_renamed: ReadSignal<Option<String>>,
+    set_default_profile: ServerAction<SetDefaultProfile>,
+    delete_profile: ServerAction<DeleteProfile>,
+) -> impl IntoView {
+    // Each form takes its own copy, since their children are moved into closures.
+    let username = profile.username;
+    let edit_username = username.clone();
+    let rename_username = username.clone();
+    let renamed_username = username.clone();
+    let default_username = username.clone();
+    let delete_username = username.clone();
+
+    view! {
+        <fieldset class="p-2 my-2 border-2 border-slate-500">
+            <legend class="text-xl font-bold">
+                <ANorm href=format!("/u/{username}")>"@" {username}</ANorm>
+                {profile.default.then_some(" (default)")}
+            </legend>
+            <CsrfActionForm action=edit_profile>
+                <input type="hidden" name="username" value=edit_username />
+                <div class="py-2">
+                    <label>
+                        "Display name: "
+                        <input
+                            type="text"
+                            name="display_name"
+                            placeholder="Display name"
+                            maxlength="30"
+                            autocomplete="off"
+                            class="p-0.5 border-2 border-slate-300"
+                            value=profile.display_name
+                        />
+                    </label>
+                </div>
+                <div class="py-2">
+                    <label>
+                        <p>"Bio:"</p>
+                        <textarea
+                            name="bio"
+                            placeholder="Bio"
+                            maxlength="500"
+                            autocomplete="off"
+                            class="p-0.5 w-full border-2 border-slate-300"
+                        >
+                            {profile.bio}
+                        </textarea>
+                    </label>
+                </div>
+                <input
+                    type="submit"
+                    value="Save"
+                    class="py-0.5 px-2 font-bold bg-green-200 hover:bg-green-400"
+                />
+            </CsrfActionForm>
+            <CsrfActionForm action=rename_profile>
+                <input type="hidden" name="old_username" value=rename_username />
+                <div class="py-2">
+                    <label>
+                        "New username: "
+                        <input
+                            type="text"
+                            name="username"
+                            placeholder="Username"
+                            required
+                            minlength=USERNAME_MIN_LEN
+                            maxlength=USERNAME_MAX_LEN
+                            pattern=username_pattern()
+                            title=username_rules()
+                            autocomplete="off"
+                            class="p-0.5 border-2 border-slate-300"
+                        />
+                    </label>
+                    " "
+                    <input
+                        type="submit"
+                        value="Change username"
+                        class="py-0.5 px-2 bg-slate-200 hover:bg-slate-400"
+                    />
+                    <Show when=move || { last_renamed.get().as_ref() == Some(&renamed_username) }>
+                        <ShowFieldError action=rename_profile field="username" />
+                    </Show>
+                    <p class="text-sm">
+                        "Links to your old username will lead to the new one until someone else takes it. Nobody else can take it for "
+                        {USERNAME_RESERVED_DAYS} " days."
+                    </p>
+                </div>
+            </CsrfActionForm>
+            <div class="flex gap-2 py-2">
+                <CsrfActionForm action=set_default_profile>
+                    <input type="hidden" name="username" value=default_username />
+                    <input
+                        type="submit"
+                        value="Make default"
+                        class="py-0.5 px-2 disabled:opacity-50 bg-slate-200 hover:bg-slate-400"
+                        disabled=profile.default
+                    />
+                </CsrfActionForm>
+                <CsrfActionForm action=delete_profile>
+                    <input type="hidden" name="username" value=delete_username />
+                    <label>
+                        <input type="checkbox" required />
+                        " I understand deleting this profile is permanent, and its usernames can be taken by others after "
+                        {USERNAME_RESERVED_DAYS}
+                        " days "
+                    </label>
+                    <input
+                        type="submit"
+                        value="Delete"
+                        class="py-0.5 px-2 bg-red-200 hover:bg-red-400"
+                    />
+                </CsrfActionForm>
+            </div>
+        </fieldset>
+    }
+}
+
+/// Page for managing the current account's profiles.
+#[component]
+pub fn ProfileSettings() -> impl IntoView {
+    let create_profile = ServerAction::<CreateProfile>::new();
+    let edit_profile = ServerAction::<EditProfile>::new();
+    let rename_profile = ServerAction::<RenameProfile>::new();
+    let set_default_profile = ServerAction::<SetDefaultProfile>::new();
+    let set_ask_for_profile_on_login = ServerAction::<SetAskForProfileOnLogin>::new();
+    let delete_profile = ServerAction::<DeleteProfile>::new();
+
+    // Remember which profile was last renamed, so a problem with the new username is shown next to
+    // the right form.
+    let (last_renamed, set_last_renamed) = signal(None::<String>);
+    Effect::new(move || {
+        if let Some(input) = rename_profile.input().get() {
+            set_last_renamed.set(Some(input.old_username));
+        }
+    });
+
+    let settings = Resource::new(
+        move || {
+            (
+                create_profile.version().get(),
+                edit_profile.version().get(),
+                rename_profile.version().get(),
+                set_default_profile.version().get(),
+                set_ask_for_profile_on_login.version().get(),
+                delete_profile.version().get(),
+            )
+        },
+        |_| get_profile_settings(),
+    );
+
+    view! {
+        <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
+            <legend class="m-2 text-2xl font-bold">Profiles</legend>
+            <p>
+                "Each profile has its own username, display name, and bio. You can switch between them at any time without logging out."
+            </p>
+            <ShowFormStatus action=edit_profile success="Profile saved." />
+            <ShowFormStatus action=rename_profile success="Username changed." />
+            <ShowActionStatus action=set_default_profile success="Default profile changed." />
+            <ShowActionStatus action=delete_profile success="Profile deleted." />
+            <Transition fallback=move || {
+                view! { <Spinner /> }
+            }>
+                {move || Suspend::new(async move {
+                    match settings.await {
+                        Ok(settings) => {
+                            let reader_mode_default = !settings
+                                .profiles
+                                .iter()
+                                .any(|profile| profile.default);
+                            view! {
+                                {settings
+                                    .profiles
+                                    .into_iter()
+                                    .map(|profile| {
+                                        view! {
+                                            <ProfileEntry
+                                                profile=profile
+                                                edit_profile=edit_profile
+                                                rename_profile=rename_profile
+                                                last_renamed=last_renamed
+                                                set_default_profile=set_default_profile
+                                                delete_profile=delete_profile
+                                            />
+                                        }
+                                    })
+                                    .collect_view()}
+                                <fieldset class="p-2 my-2 border-2 border-slate-500">
+                                    <legend class="text-xl font-bold">"Login"</legend>
+                                    <CsrfActionForm action=set_default_profile>
+                                        <input type="hidden" name="username" value="" />
+                                        <input
+                                            type="submit"
+                                            value="Make reader mode default"
+                                            class="py-0.5 px-2 disabled:opacity-50 bg-slate-200 hover:bg-slate-400"
+                                            disabled=reader_mode_default
+                                        />
+                                    </CsrfActionForm>
+                                    <CsrfActionForm action=set_ask_for_profile_on_login>
+                                        <div class="py-2">
+                                            <label>
+                                                <input
+                                                    type="checkbox"
+                                                    name="ask"
+                                                    value="true"
+                                                    checked=settings.ask_for_profile_on_login
+                                                />
+                                                " Ask which profile to use every time I log in "
+                                            </label>
+                                            <input
+                                                type="submit"
+                                                value="Save"
+                                                class="py-0.5 px-2 bg-slate-200 hover:bg-slate-400"
+                                            />
+                                        </div>
+                                    </CsrfActionForm>
+                                    <ShowActionStatus
+                                        action=set_ask_for_profile_on_login
+                                        success="Login setting saved."
+                                    />
+                                </fieldset>
+                            }
+                                .into_any()
+                        }
+                        Err(err) => view! { <ShowServerFnError error=err /> }.into_any(),
+                    }
+                })}
+            </Transition>
+            <CsrfActionForm action=create_profile>
+                <fieldset class="p-2 my-2 border-2 border-slate-500">
+                    <legend class="text-xl font-bold">New profile</legend>
+                    <div class="py-2">
+                        <label for="new_username">"Username: "</label>
+                        <input
+                            type="text"
+                            name="username"
+                            id="new_username"
+                            placeholder="Username"
+                            required
+                            minlength=USERNAME_MIN_LEN
+                            maxlength=USERNAME_MAX_LEN
+                            pattern=username_pattern()
+                            title=username_rules()
+                            class="p-0.5 border-2 border-slate-300"
+                        />
+                        <ShowFieldError action=create_profile field="username" />
+                    </div>
+                    <div class="py-2">
+                        <label for="new_display_name">"Display name: "</label>
+                        <input
+                            type="text"
+                            name="display_name"
+                            id="new_display_name"
+                            placeholder="Display name"
+                            maxlength="30"
+                            autocomplete="off"
+                            class="p-0.5 border-2 border-slate-300"
+                        />
+                    </div>
+                    <div class="py-2">
+                        <p>
+                            <label for="new_bio">"Bio:"</label>
+                        </p>
+                        <textarea
+                            name="bio"
+                            id="new_bio"
+                            placeholder="Bio"
+                            maxlength="500"
+                            autocomplete="off"
+                            class="p-0.5 w-full border-2 border-slate-300"
+                        ></textarea>
+                    </div>
+                    <div class="py-2">
+                        <label for="new_make_default">"Make default: "</label>
+                        <input
+                            type="checkbox"
+                            name="make_default"
+                            id="new_make_default"
+                            value="true"
+                        />
+                    </div>
+                    <input
+                        type="submit"
+                        value="Create profile"
+                        class="py-0.5 px-2 font-bold bg-green-200 hover:bg-green-400"
+                    />
+                    <ShowFormStatus action=create_profile success="Profile created." />
+                </fieldset>
+            </CsrfActionForm>
+        </fieldset>
+    }
+}
@@ -0,0 +1,184 @@
+/// List of where an account is logged in.
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos_router::components::*;
+use leptos_router::{MatchNestedRoutes, path};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::cookie::*;
+
+    pub use actix_web::HttpRequest;
+    pub use leptos_actix::extract;
+}
+
+/// Route definitions for session management.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("sessions") view=SessionSettings /> }.into_inner()
+}
+
+/// A session as shown to its owner.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SessionEntry {
+    pub handle: String,
+    /// Whether this is the session making the request.
+    pub current: bool,
+    pub created_secs_ago: i64,
+    pub last_seen_secs_ago: i64,
+    pub user_agent: String,
+    pub ip: String,
+}
+
+/// Get every session of the logged in account.
+#[server(client = CsrfClient)]
+async fn get_sessions() -> Result<Vec<SessionEntry>, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let now = unix_time();
+
+    Ok(app_state
+        .list_sessions(session.account_id)
+        .await?
+        .into_iter()
+        .map(|details| SessionEntry {
+            current: details.session_id == session.session_id,
+            handle: details.handle,
+            created_secs_ago: now - details.created_at,
+            last_seen_secs_ago: now - details.last_seen,
+            user_agent: details.user_agent,
+            ip: details.ip,
+        })
+        .collect())
+}
+
+/// End one of the logged in account's sessions.
+#[server(client = CsrfClient)]
+async fn revoke_session(handle: String) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request.clone()).await?;
+
+    let current = app_state
+        .list_sessions(session.account_id)
+        .await?
+        .into_iter()
+        .any(|details| details.handle == handle && details.session_id == session.session_id);
+
+    if current {
+        // Also clear the cookie, same as logging out.
+        let response_options = use_response_options()?;
+        app_state.end_session(request, &response_options).await?;
+        leptos_actix::redirect("/");
+    } else if !app_state
+        .end_session_by_handle(session.account_id, &handle)
+        .await?
+    {
+        return Err(ServerFnError::new(
+            "That session doesn't exist. It may have already ended.",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Describe roughly how long ago something happened.
+fn format_age(secs: i64) -> String {
+    let (amount, unit) = match secs {
+        ..60 => return String::from("just now"),
+        60..3600 => (secs / 60, "minute"),
+        3600..86400 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    if amount == 1 {
+        format!("1 {unit} ago")
+    } else {
+        format!("{amount} {unit}s ago")
+    }
+}
+
+/// Page listing the current account's sessions.
+#[component]
+pub fn SessionSettings() -> impl IntoView {
+    let revoke_session = ServerAction::<RevokeSession>::new();
+    let sessions = Resource::new(move || revoke_session.version().get(), |_| get_sessions());
+
+    view! {
+        <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
+            <legend class="m-2 text-2xl font-bold">Sessions</legend>
+            <p>
+                "These are the devices and browsers you're logged in on. If you don't recognize one, log it out."
+            </p>
+            <ShowActionStatus action=revoke_session success="Session logged out." />
+            <Transition fallback=move || {
+                view! { <Spinner /> }
+            }>
+                {move || Suspend::new(async move {
+                    match sessions.await {
+                        Ok(sessions) => {
+                            view! {
+                                <table class="my-2 table-auto">
+                                    <thead>
+                                        <tr class="text-left">
+                                            <th class="px-2">"Device"</th>
+                                            <th class="px-2">"IP address"</th>
+                                            <th class="px-2">"Logged in"</th>
+                                            <th class="px-2">"Last seen"</th>
+                                            <th class="px-2"></th>
+                                        </tr>
+                                    </thead>
+                                    <tbody>
+                                        {sessions
+                                            .into_iter()
+                                            .map(|session| {
+                                                view! {
+                                                    <tr class=("font-bold", session.current)>
+                                                        <td class="px-2">
+                                                            {session.user_agent}
+                                                            {session.current.then_some(" (this device)")}
+                                                        </td>
+                                                        <td class="px-2">{session.ip}</td>
+                                                        <td class="px-2">{format_age(session.created_secs_ago)}</td>
+                                                        <td class="px-2">
+                                                            {format_age(session.last_seen_secs_ago)}
+                                                        </td>
+                                                        <td class="px-2">
+                                                            <CsrfActionForm action=revoke_session>
+                                                                <input type="hidden" name="handle" value=session.handle />
+                                                                <input
+                                                                    type="submit"
+                                                                    value="Log out"
+                                                                    class="py-0.5 px-2 bg-red-200 hover:bg-red-400"
+                                                                />
+                                                            </CsrfActionForm>
+                                                        </td>
+                                                    </tr>
+                                                }
+                                            })
+                                            .collect_view()}
+                                    </tbody>
+                                </table>
+                            }
+                                .into_any()
+                        }
+                        Err(err) => view! { <ShowServerFnError error=err /> }.into_any(),
+                    }
+                })}
+            </Transition>
+            <p>
+                "To log out of every session at once, "
+                <ANorm href="/auth/logout">"log out everywhere"</ANorm> "."
+            </p>
+        </fieldset>
+    }
+}
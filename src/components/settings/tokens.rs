@@ -0,0 +1,510 @@
+/// Management of the personal API tokens scripts and bots use to act as a profile.
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos::server_fn::ServerFn;
+use leptos::server_fn::codec::GetUrl;
+use leptos_router::components::*;
+use leptos_router::{MatchNestedRoutes, path};
+use serde::{Deserialize, Serialize};
+
+const TOKEN_NAME_MAX_LEN: usize = 50;
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::api_token::*;
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::uuid_codec::{decode_uuid, encode_uuid};
+
+    pub use actix_web::HttpRequest;
+    pub use leptos_actix::extract;
+    pub use uuid::Uuid;
+
+    /// Most tokens an account can have at once.
+    pub const MAX_TOKENS_PER_ACCOUNT: i64 = 20;
+}
+
+/// Route definitions for API token management.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("tokens") view=ApiTokenSettings /> }.into_inner()
+}
+
+/// An API token as shown to its owner. The token itself is only shown once, when created.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApiTokenEntry {
+    pub id: String,
+    pub name: String,
+    /// Username of the profile the token acts as.
+    pub profile: String,
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+/// The logged in account's API tokens, and the profiles new ones can act as.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApiTokens {
+    pub tokens: Vec<ApiTokenEntry>,
+    pub profiles: Vec<String>,
+}
+
+/// Who an API token acts as, for scripts to check their token with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApiIdentity {
+    pub username: String,
+    pub display_name: String,
+    /// Scopes of the token. Browser sessions have every scope.
+    pub scopes: Vec<String>,
+}
+
+/// Get the logged in account's API tokens, and its profiles.
+#[server(client = CsrfClient)]
+async fn get_api_tokens() -> Result<ApiTokens, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let tokens = sqlx::query_as::<_, (Uuid, String, String, Vec<String>, String, Option<String>)>(
+        r#"
+        select
+          api_token.id,
+          api_token.name,
+          profile.username::text,
+          api_token.scopes,
+          api_token.created_at::date::text,
+          api_token.last_used_at::date::text
+        from
+          api_token
+          join profile on api_token.profile_id = profile.id
+        where api_token.account_id = $1
+        order by api_token.created_at
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get API tokens from DB: {err}"
+        )))
+    })?
+    .into_iter()
+    .map(
+        |(id, name, profile, scopes, created_at, last_used_at)| ApiTokenEntry {
+            id: encode_uuid(id),
+            name,
+            profile,
+            scopes,
+            created_at,
+            last_used_at,
+        },
+    )
+    .collect();
+
+    let profiles = sqlx::query_as::<_, (String,)>(
+        r#"
+        select username::text
+        from profile
+        where account_id = $1
+        order by created_at
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get profiles from DB: {err}"
+        )))
+    })?
+    .into_iter()
+    .map(|(username,)| username)
+    .collect();
+
+    Ok(ApiTokens { tokens, profiles })
+}
+
+/// Create an API token acting as one of the logged in account's profiles, returning the token.
+/// Only its hash is stored, so it has to be shown to the user now or never.
+#[server(client = CsrfClient)]
+async fn create_api_token(
+    name: String,
+    profile: String,
+    read: Option<String>,
+    write: Option<String>,
+) -> Result<String, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let name: String = name.trim().chars().take(TOKEN_NAME_MAX_LEN).collect();
+    if name.is_empty() {
+        return Err(ServerFnError::new("Give the token a name."));
+    }
+    let scopes: Vec<&str> = [(ApiScope::Read, read), (ApiScope::Write, write)]
+        .into_iter()
+        .filter(|(_, checked)| checked.is_some())
+        .map(|(scope, _)| scope.name())
+        .collect();
+    if scopes.is_empty() {
+        return Err(ServerFnError::new("Pick at least one scope."));
+    }
+
+    let mut transaction = app_state.db_pool.begin().await.or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to create transaction for API token: {err}"
+        )))
+    })?;
+
+    // Lock the account, so tokens created at the same time can't all get in under the limit.
+    let (count,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        select (select count(*) from api_token where account_id = account.id)
+        from account
+        where id = $1
+        for update
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_one(&mut *transaction)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get API tokens from DB: {err}"
+        )))
+    })?;
+    if count >= MAX_TOKENS_PER_ACCOUNT {
+        return Err(ServerFnError::new(format!(
+            "You can have at most {MAX_TOKENS_PER_ACCOUNT} API tokens. Revoke one first."
+        )));
+    }
+
+    let (token, token_hash) = generate_token();
+    if sqlx::query(
+        r#"
+        insert into api_token (account_id, profile_id, name, token_hash, scopes)
+        select $1, id, $2, $3, $4
+        from profile
+        where username = $5
+          and account_id = $1
+        "#,
+    )
+    .bind(session.account_id)
+    .bind(&name)
+    .bind(token_hash)
+    .bind(scopes)
+    .bind(&profile)
+    .execute(&mut *transaction)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to create API token: {err}"
+        )))
+    })?
+    .rows_affected()
+        == 0
+    {
+        return Err(ServerFnError::new("You don't have that profile."));
+    }
+
+    transaction.commit().await.or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to commit API token: {err}"
+        )))
+    })?;
+
+    Ok(token)
+}
+
+/// Revoke one of the logged in account's API tokens. It stops working right away.
+#[server(client = CsrfClient)]
+async fn revoke_api_token(id: String) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+    let id =
+        decode_uuid(&id).or_else(|_| Err(ServerFnError::new("That API token doesn't exist.")))?;
+
+    if sqlx::query(
+        r#"
+        delete from api_token
+        where id = $1
+          and account_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(session.account_id)
+    .execute(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to revoke API token: {err}"
+        )))
+    })?
+    .rows_affected()
+        == 0
+    {
+        return Err(ServerFnError::new("That API token doesn't exist."));
+    }
+
+    Ok(())
+}
+
+/// Get who the request acts as. This is a GET endpoint with a fixed URL, `/api/whoami`, so
+/// scripts can check their token.
+#[server(endpoint = "whoami", input = GetUrl)]
+async fn get_api_identity() -> Result<ApiIdentity, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_scope(request, None).await?;
+
+    let scopes = match session.api_token {
+        Some(api_token) => api_token.scopes,
+        None => ApiScope::ALL.to_vec(),
+    };
+    Ok(ApiIdentity {
+        username: session.username,
+        display_name: session.display_name,
+        scopes: scopes
+            .into_iter()
+            .map(|scope| scope.name().to_string())
+            .collect(),
+    })
+}
+
+/// Change the display name and bio of the profile the request acts as. This has a fixed URL,
+/// `/api/edit_current_profile`, so scripts can call it with a token that has the write scope.
+#[server(endpoint = "edit_current_profile", client = CsrfClient)]
+async fn edit_current_profile(
+    display_name: Option<String>,
+    bio: Option<String>,
+) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_profile(request, ApiScope::Write).await?;
+    let display_name = display_name.filter(|display_name| !display_name.is_empty());
+
+    sqlx::query(
+        r#"
+        update profile
+        set display_name = $3, bio = $4
+        where account_id = $1
+          and username = $2
+        "#,
+    )
+    .bind(session.account_id)
+    .bind(&session.username)
+    .bind(&display_name)
+    .bind(bio.filter(|bio| !bio.is_empty()))
+    .execute(&app_state.db_pool)
+    .await
+    .or_else(|err| Err(ServerFnError::new(format!("Failed to edit profile: {err}"))))?;
+
+    // Keep the display name shown for a browser session up to date.
+    if session.api_token.is_none() {
+        app_state
+            .set_session_profile(&session.session_id, Some(session.username), display_name)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Page listing the current account's API tokens, and creating new ones.
+#[component]
+pub fn ApiTokenSettings() -> impl IntoView {
+    let create_api_token = ServerAction::<CreateApiToken>::new();
+    let revoke_api_token = ServerAction::<RevokeApiToken>::new();
+    let tokens = Resource::new(
+        move || {
+            (
+                create_api_token.version().get(),
+                revoke_api_token.version().get(),
+            )
+        },
+        |_| get_api_tokens(),
+    );
+
+    view! {
+        <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
+            <legend class="m-2 text-2xl font-bold">"API tokens"</legend>
+            <p>
+                "Scripts and bots can act as one of your profiles with an API token, sent in an "
+                <code>"Authorization: Bearer"</code>
+                " header. Tokens can't change your account settings. To check a token, request "
+                <code>{GetApiIdentity::PATH}</code>
+                " with it. Tokens with the write scope can also change their profile's display name and bio by posting them to "
+                <code>{EditCurrentProfile::PATH}</code> "."
+            </p>
+            <ShowActionStatus action=revoke_api_token success="API token revoked." />
+            <Transition fallback=move || {
+                view! { <Spinner /> }
+            }>
+                {move || Suspend::new(async move {
+                    match tokens.await {
+                        Ok(ApiTokens { tokens, profiles }) => {
+                            view! {
+                                {if tokens.is_empty() {
+                                    view! {
+                                        <p class="my-2">
+                                            "You haven't created any API tokens yet."
+                                        </p>
+                                    }
+                                        .into_any()
+                                } else {
+                                    view! {
+                                        <table class="my-2 table-auto">
+                                            <thead>
+                                                <tr class="text-left">
+                                                    <th class="px-2">"Name"</th>
+                                                    <th class="px-2">"Profile"</th>
+                                                    <th class="px-2">"Scopes"</th>
+                                                    <th class="px-2">"Created"</th>
+                                                    <th class="px-2">"Last used"</th>
+                                                    <th class="px-2"></th>
+                                                </tr>
+                                            </thead>
+                                            <tbody>
+                                                {tokens
+                                                    .into_iter()
+                                                    .map(|token| {
+                                                        view! {
+                                                            <tr>
+                                                                <td class="px-2">{token.name}</td>
+                                                                <td class="px-2">{token.profile}</td>
+                                                                <td class="px-2">{token.scopes.join(", ")}</td>
+                                                                <td class="px-2">{token.created_at}</td>
+                                                                <td class="px-2">
+                                                                    {token
+                                                                        .last_used_at
+                                                                        .unwrap_or_else(|| String::from("never"))}
+                                                                </td>
+                                                                <td class="px-2">
+                                                                    <CsrfActionForm action=revoke_api_token>
+                                                                        <input type="hidden" name="id" value=token.id />
+                                                                        <input
+                                                                            type="submit"
+                                                                            value="Revoke"
+                                                                            class="py-0.5 px-2 bg-red-200 hover:bg-red-400"
+                                                                        />
+                                                                    </CsrfActionForm>
+                                                                </td>
+                                                            </tr>
+                                                        }
+                                                    })
+                                                    .collect_view()}
+                                            </tbody>
+                                        </table>
+                                    }
+                                        .into_any()
+                                }}
+                                <NewApiToken action=create_api_token profiles />
+                            }
+                                .into_any()
+                        }
+                        Err(err) => view! { <ShowServerFnError error=err /> }.into_any(),
+                    }
+                })}
+            </Transition>
+        </fieldset>
+    }
+}
+
+/// Form for creating an API token, which shows the token once it's created.
+#[component]
+fn NewApiToken(action: ServerAction<CreateApiToken>, profiles: Vec<String>) -> impl IntoView {
+    if profiles.is_empty() {
+        return view! {
+            <p class="my-2">
+                "API tokens act as a profile, so you need to "
+                <ANorm href="/settings/profiles">"create a profile"</ANorm>
+                " before you can create one."
+            </p>
+        }
+        .into_any();
+    }
+
+    view! {
+        <CsrfActionForm action>
+            <fieldset class="p-2 my-2 border-2 border-slate-500">
+                <legend class="text-xl font-bold">"New API token"</legend>
+                <div class="py-2">
+                    <label for="token_name">"Name: "</label>
+                    <input
+                        type="text"
+                        name="name"
+                        id="token_name"
+                        placeholder="e.g. Tally bot"
+                        maxlength=TOKEN_NAME_MAX_LEN
+                        autocomplete="off"
+                        required
+                        class="p-0.5 border-2 border-slate-300"
+                    />
+                </div>
+                <div class="py-2">
+                    <label for="token_profile">"Acts as: "</label>
+                    <select
+                        name="profile"
+                        id="token_profile"
+                        class="p-0.5 border-2 border-slate-300"
+                    >
+                        {profiles
+                            .into_iter()
+                            .map(|username| {
+                                view! { <option value=username.clone()>{username.clone()}</option> }
+                            })
+                            .collect_view()}
+                    </select>
+                </div>
+                <div class="py-2">
+                    <input type="checkbox" name="read" id="token_read" checked />
+                    <label for="token_read">" Read: see what the profile can see"</label>
+                </div>
+                <div class="py-2">
+                    <input type="checkbox" name="write" id="token_write" />
+                    <label for="token_write">
+                        " Write: change the profile's display name and bio"
+                    </label>
+                </div>
+                <input
+                    type="submit"
+                    value="Create API token"
+                    class="py-0.5 px-2 font-bold bg-green-200 hover:bg-green-400"
+                />
+                <Show
+                    when=move || { !action.pending().get() }
+                    fallback=move || view! { <Spinner /> }
+                >
+                    {move || match action.value().get() {
+                        Some(Ok(token)) => {
+                            view! {
+                                <div class="p-2 my-2 border-2 border-amber-500">
+                                    <p class="font-bold">
+                                        "Copy this API token now. It won't be shown again."
+                                    </p>
+                                    <code class="select-all">{token}</code>
+                                </div>
+                            }
+                                .into_any()
+                        }
+                        Some(Err(err)) => view! { <ShowServerFnError error=err /> }.into_any(),
+                        None => view! { "" }.into_any(),
+                    }}
+                </Show>
+            </fieldset>
+        </CsrfActionForm>
+    }
+    .into_any()
+}
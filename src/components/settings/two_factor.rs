@@ -0,0 +1,396 @@
+/// Setting up an authenticator app (TOTP) as a second factor for email and other sites' logins.
+use crate::components::csrf::{CsrfActionForm, CsrfClient};
+use crate::components::ui::*;
+
+use leptos::prelude::*;
+use leptos_router::components::*;
+use leptos_router::{MatchNestedRoutes, path};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::key;
+    pub use crate::ssr::rate_limit::*;
+    pub use crate::ssr::totp::*;
+    pub use crate::ssr::uuid_codec::encode_uuid;
+
+    pub use actix_web::HttpRequest;
+    pub use fred::prelude::KeysInterface;
+    pub use leptos_actix::extract;
+
+    /// How long the user has to scan the QR code and enter a code to finish setting up.
+    pub const TOTP_ENROLMENT_TIMEOUT_SEC: i64 = 10 * 60;
+}
+
+/// Route definitions for two-factor settings.
+#[component(transparent)]
+pub fn Routes() -> impl MatchNestedRoutes + Clone {
+    view! { <Route path=path!("two-factor") view=TwoFactorSettings /> }.into_inner()
+}
+
+/// Whether the logged in account has a second factor, and how many ways back in it has left.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+}
+
+/// A new shared secret for an authenticator app to scan or have typed in.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TotpEnrolment {
+    pub qr_code_svg: String,
+    pub secret: String,
+}
+
+/// Get the logged in account's second factor status.
+#[server(client = CsrfClient)]
+async fn get_two_factor() -> Result<TwoFactorStatus, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let (enabled, recovery_codes_left) = sqlx::query_as::<_, (bool, i64)>(
+        r#"
+        select
+          exists (select 1 from totp_credential where account_id = $1),
+          (select count(*) from totp_recovery_code where account_id = $1 and used_at is null)
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_one(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get second factor from DB: {err}"
+        )))
+    })?;
+
+    Ok(TwoFactorStatus {
+        enabled,
+        recovery_codes_left,
+    })
+}
+
+/// Start setting up an authenticator app. The secret isn't used until a code from the app
+/// confirms it was set up right.
+#[server(client = CsrfClient)]
+async fn start_totp_enrolment() -> Result<TotpEnrolment, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    let (email,) = sqlx::query_as::<_, (String,)>(
+        r#"
+        select email::text
+        from account
+        where id = $1
+        "#,
+    )
+    .bind(session.account_id)
+    .fetch_one(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get account from DB: {err}"
+        )))
+    })?;
+
+    let secret = generate_secret();
+    let qr_code_svg = qr_code_svg(decode_secret(&secret)?, &email)?;
+    app_state
+        .valkey_pool
+        .set::<(), _, _>(
+            key::totp_enrolment(&encode_uuid(session.account_id)),
+            &secret,
+            Some(fred::types::Expiration::EX(TOTP_ENROLMENT_TIMEOUT_SEC)),
+            None,
+            false,
+        )
+        .await?;
+
+    Ok(TotpEnrolment {
+        qr_code_svg,
+        secret,
+    })
+}
+
+/// Finish setting up an authenticator app with a code from it, turning on the second factor.
+/// Returns the new recovery codes, which can't be shown again.
+#[server(client = CsrfClient)]
+async fn confirm_totp_enrolment(code: String) -> Result<Vec<String>, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+    SECOND_FACTOR_PER_ACCOUNT
+        .check(&app_state.valkey_pool, &encode_uuid(session.account_id))
+        .await?;
+
+    let key = key::totp_enrolment(&encode_uuid(session.account_id));
+    let secret = app_state
+        .valkey_pool
+        .get::<Option<String>, _>(&key)
+        .await?
+        .ok_or_else(|| ServerFnError::new("Setting up took too long. Start over."))?;
+    let secret = decode_secret(&secret)?;
+
+    // The step is saved too, so the code that turned it on can't also be used to log in.
+    let step = matching_step(secret.clone(), &code)?.ok_or_else(|| {
+        ServerFnError::new("That code is wrong. Check the time on your device is right.")
+    })?;
+
+    let mut tx = app_state.db_pool.begin().await.or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to start transaction: {err}"
+        )))
+    })?;
+    let inserted = sqlx::query(
+        r#"
+        insert into totp_credential (account_id, secret, last_used_step)
+        values ($1, $2, $3)
+        on conflict (account_id) do nothing
+        "#,
+    )
+    .bind(session.account_id)
+    .bind(secret)
+    .bind(step)
+    .execute(&mut *tx)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to save authenticator app: {err}"
+        )))
+    })?
+    .rows_affected();
+    if inserted == 0 {
+        return Err(ServerFnError::new(
+            "An authenticator app is already set up. Turn it off first to use a different one.",
+        ));
+    }
+    let recovery_codes = replace_recovery_codes(&mut tx, session.account_id).await?;
+    tx.commit().await.or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to commit transaction: {err}"
+        )))
+    })?;
+
+    app_state.valkey_pool.del::<(), _>(&key).await?;
+
+    Ok(recovery_codes)
+}
+
+/// Replace the logged in account's recovery codes, given a current code.
+#[server(client = CsrfClient)]
+async fn regenerate_recovery_codes(code: String) -> Result<Vec<String>, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    if !check_second_factor(&app_state, session.account_id, &code).await? {
+        return Err(ServerFnError::new(
+            "That code is wrong or was already used.",
+        ));
+    }
+
+    // The old codes are only removed along with the new ones being saved.
+    let mut tx = app_state.db_pool.begin().await.or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to start transaction: {err}"
+        )))
+    })?;
+    let recovery_codes = replace_recovery_codes(&mut tx, session.account_id).await?;
+    tx.commit().await.or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to commit transaction: {err}"
+        )))
+    })?;
+
+    Ok(recovery_codes)
+}
+
+/// Turn off the second factor, given a current code.
+#[server(client = CsrfClient)]
+async fn disable_totp(code: String) -> Result<(), ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    let session = app_state.require_session(request).await?;
+
+    if !check_second_factor(&app_state, session.account_id, &code).await? {
+        return Err(ServerFnError::new(
+            "That code is wrong or was already used.",
+        ));
+    }
+
+    // Recovery codes go with it.
+    sqlx::query(
+        r#"
+        delete from totp_credential
+        where account_id = $1
+        "#,
+    )
+    .bind(session.account_id)
+    .execute(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to remove authenticator app: {err}"
+        )))
+    })?;
+
+    Ok(())
+}
+
+/// Freshly made recovery codes, for the user to save.
+#[component]
+fn RecoveryCodes(codes: Vec<String>) -> impl IntoView {
+    view! {
+        <div class="p-2 my-2 border-2 border-amber-500">
+            <p class="font-bold">
+                "Save these recovery codes somewhere safe. Each one works once in place of a code from your app, and they won't be shown again."
+            </p>
+            <ul class="font-mono">
+                {codes.into_iter().map(|code| view! { <li>{code}</li> }).collect_view()}
+            </ul>
+        </div>
+    }
+}
+
+/// Input for a code from the authenticator app.
+#[component]
+fn CodeInput(#[prop(optional)] allow_recovery: bool) -> impl IntoView {
+    view! {
+        <input
+            type="text"
+            name="code"
+            placeholder=if allow_recovery { "code or recovery code" } else { "code" }
+            class="px-1 bg-gray-200 border border-gray-500"
+            required
+            autocomplete="one-time-code"
+        />
+    }
+}
+
+/// Page for setting up or changing the second factor.
+#[component]
+pub fn TwoFactorSettings() -> impl IntoView {
+    let start_totp_enrolment = ServerAction::<StartTotpEnrolment>::new();
+    let confirm_totp_enrolment = ServerAction::<ConfirmTotpEnrolment>::new();
+    let regenerate_recovery_codes = ServerAction::<RegenerateRecoveryCodes>::new();
+    let disable_totp = ServerAction::<DisableTotp>::new();
+    let status = Resource::new(
+        move || {
+            (
+                confirm_totp_enrolment.version().get(),
+                regenerate_recovery_codes.version().get(),
+                disable_totp.version().get(),
+            )
+        },
+        |_| get_two_factor(),
+    );
+    let new_recovery_codes = move || {
+        confirm_totp_enrolment
+            .value()
+            .get()
+            .or_else(|| regenerate_recovery_codes.value().get())
+            .and_then(Result::ok)
+    };
+
+    view! {
+        <fieldset class="px-2 pt-1 pb-2 border-2 border-slate-500">
+            <legend class="m-2 text-2xl font-bold">"Two-factor authentication"</legend>
+            <p>
+                "With an authenticator app set up, logging in by email or with another site also asks for a code from the app, so someone who gets into your email, or the other site's account, can't get into your account too. Passkeys are already tied to your device, so logging in with one doesn't ask."
+            </p>
+            {move || new_recovery_codes().map(|codes| view! { <RecoveryCodes codes /> })}
+            <ShowActionStatus action=disable_totp success="Authenticator app turned off." />
+            <Transition fallback=move || {
+                view! { <Spinner /> }
+            }>
+                {move || Suspend::new(async move {
+                    match status.await {
+                        Ok(status) if status.enabled => {
+                            view! {
+                                <p class="my-2">
+                                    "An authenticator app is set up. You have "
+                                    {status.recovery_codes_left} " unused recovery codes."
+                                </p>
+                                <CsrfActionForm action=regenerate_recovery_codes>
+                                    <div class="flex gap-2 my-2">
+                                        <CodeInput allow_recovery=true />
+                                        <input
+                                            type="submit"
+                                            value="Get new recovery codes"
+                                            class="py-0.5 px-2 bg-green-200 hover:bg-green-400"
+                                        />
+                                        <ShowActionStatus action=regenerate_recovery_codes />
+                                    </div>
+                                </CsrfActionForm>
+                                <CsrfActionForm action=disable_totp>
+                                    <div class="flex gap-2 my-2">
+                                        <CodeInput allow_recovery=true />
+                                        <input
+                                            type="submit"
+                                            value="Turn off authenticator app"
+                                            class="py-0.5 px-2 bg-red-200 hover:bg-red-400"
+                                        />
+                                    </div>
+                                </CsrfActionForm>
+                            }
+                                .into_any()
+                        }
+                        Ok(_) => {
+                            view! {
+                                <CsrfActionForm action=start_totp_enrolment>
+                                    <input
+                                        type="submit"
+                                        value="Set up an authenticator app"
+                                        class="py-0.5 px-2 my-2 font-bold bg-green-200 hover:bg-green-400"
+                                    />
+                                </CsrfActionForm>
+                                {move || match start_totp_enrolment.value().get() {
+                                    Some(Ok(enrolment)) => {
+                                        view! {
+                                            <p>
+                                                "Scan this QR code with your authenticator app, or enter the key below it by hand. Then enter the code the app shows to finish."
+                                            </p>
+                                            <div class="my-2 w-52" inner_html=enrolment.qr_code_svg />
+                                            <p class="font-mono">{enrolment.secret}</p>
+                                            <CsrfActionForm action=confirm_totp_enrolment>
+                                                <div class="flex gap-2 my-2">
+                                                    <CodeInput />
+                                                    <input
+                                                        type="submit"
+                                                        value="Turn on"
+                                                        class="py-0.5 px-2 font-bold bg-green-200 hover:bg-green-400"
+                                                    />
+                                                    <ShowActionStatus action=confirm_totp_enrolment />
+                                                </div>
+                                            </CsrfActionForm>
+                                        }
+                                            .into_any()
+                                    }
+                                    Some(Err(err)) => {
+                                        view! { <ShowServerFnError error=err /> }.into_any()
+                                    }
+                                    None => view! { "" }.into_any(),
+                                }}
+                            }
+                                .into_any()
+                        }
+                        Err(err) => view! { <ShowServerFnError error=err /> }.into_any(),
+                    }
+                })}
+            </Transition>
+        </fieldset>
+    }
+}
@@ -1,5 +1,7 @@
 /// Common UI building blocks.
 use leptos::prelude::*;
+use leptos::server_fn::ServerFn;
+use leptos::server_fn::error::NoCustomError;
 use leptos_router::components::*;
 
 /// Normal link.
@@ -68,3 +70,46 @@ pub fn ShowError(error: String) -> impl IntoView {
         </span>
     }
 }
+
+/// Display a spinner while a server action is pending, then its error or a success message.
+#[component]
+pub fn ShowActionStatus<S>(
+    action: ServerAction<S>,
+    #[prop(optional)] success: &'static str,
+) -> impl IntoView
+where
+    S: ServerFn<Error = NoCustomError> + Send + Sync + Clone + 'static,
+    S::Output: Clone + Send + Sync + 'static,
+{
+    view! {
+        <Show when=move || { !action.pending().get() } fallback=move || view! { <Spinner /> }>
+            {move || match action.value().get() {
+                Some(Err(err)) => view! { <ShowServerFnError error=err /> }.into_any(),
+                Some(Ok(_)) => view! { {success} }.into_any(),
+                None => view! { "" }.into_any(),
+            }}
+        </Show>
+    }
+}
+
+/// Like ShowActionStatus, for actions that do more than call a single server function, e.g. ones
+/// that also talk to browser APIs.
+#[component]
+pub fn ShowClientActionStatus<I, O>(
+    action: Action<I, Result<O, ServerFnError>>,
+    #[prop(optional)] success: &'static str,
+) -> impl IntoView
+where
+    I: Send + Sync + 'static,
+    O: Clone + Send + Sync + 'static,
+{
+    view! {
+        <Show when=move || { !action.pending().get() } fallback=move || view! { <Spinner /> }>
+            {move || match action.value().get() {
+                Some(Err(err)) => view! { <ShowServerFnError error=err /> }.into_any(),
+                Some(Ok(_)) => view! { {success} }.into_any(),
+                None => view! { "" }.into_any(),
+            }}
+        </Show>
+    }
+}
@@ -0,0 +1,139 @@
+/// Username rules, and checking whether a username is free to take.
+use crate::components::csrf::CsrfClient;
+
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+mod ssr {
+    pub use crate::ssr::app_state::*;
+    pub use crate::ssr::rate_limit::*;
+    pub use crate::ssr::request::client_ip;
+    pub use crate::ssr::username::*;
+
+    pub use actix_web::HttpRequest;
+    pub use leptos_actix::extract;
+}
+
+pub const USERNAME_MIN_LEN: usize = 5;
+pub const USERNAME_MAX_LEN: usize = 20;
+/// How long a profile's old usernames stay reserved after it's renamed or deleted. Must match
+/// `username_recently_used` in the database.
+pub const USERNAME_RESERVED_DAYS: i64 = 90;
+
+/// The `pattern` attribute for username inputs.
+pub fn username_pattern() -> String {
+    format!(
+        "[a-z][a-z0-9]{{{},{}}}",
+        USERNAME_MIN_LEN - 1,
+        USERNAME_MAX_LEN - 1
+    )
+}
+
+/// The rules usernames follow, as the `title` of username inputs.
+pub fn username_rules() -> String {
+    format!(
+        "starts with a letter, has only lowercase letters and numbers, and is between \
+         {USERNAME_MIN_LEN} and {USERNAME_MAX_LEN} characters long"
+    )
+}
+
+/// Whether a username can be used, and alternatives if not.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UsernameAvailability {
+    /// Why the username can't be used, or None if it's free.
+    pub problem: Option<String>,
+    /// Similar usernames that are free, if this one isn't.
+    pub suggestions: Vec<String>,
+}
+
+/// Check whether a username can be taken, suggesting free alternatives if not.
+#[server(client = CsrfClient)]
+pub async fn check_username(username: String) -> Result<UsernameAvailability, ServerFnError> {
+    use self::ssr::*;
+
+    let request: HttpRequest = extract().await?;
+    let app_state = use_app_state()?;
+    USERNAME_CHECK_PER_IP
+        .check(&app_state.valkey_pool, &client_ip(&request))
+        .await?;
+
+    // Nothing useful can come of anything much longer.
+    let username: String = username.chars().take(USERNAME_MAX_LEN * 2).collect();
+
+    let rules = UsernameRules::load(&app_state.db_pool)
+        .await
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Couldn't get username rules: {err}"
+            )))
+        })?;
+
+    let problem = match (username_problem(&username), rules.problem(&username)) {
+        (Some(problem), _) => Some(problem),
+        (None, Some(problem)) => Some(problem.message()),
+        (None, None) => {
+            let (taken, recently_used) = sqlx::query_as::<_, (bool, bool)>(
+                r#"
+                select
+                  exists(select 1 from profile where username = $1),
+                  username_recently_used($1, null)
+                "#,
+            )
+            .bind(&username)
+            .fetch_one(&app_state.db_pool)
+            .await
+            .or_else(|err| {
+                Err(ServerFnError::new(format!(
+                    "Couldn't check username in DB: {err}"
+                )))
+            })?;
+            if taken {
+                Some(String::from("That username is taken."))
+            } else if recently_used {
+                Some(String::from(
+                    "Another profile used that username until recently, so it can't be taken yet.",
+                ))
+            } else {
+                None
+            }
+        }
+    };
+
+    if problem.is_none() {
+        return Ok(UsernameAvailability {
+            problem,
+            suggestions: vec![],
+        });
+    }
+
+    let candidates = suggestion_candidates(&username, &rules);
+    let taken: Vec<String> = sqlx::query_as::<_, (String,)>(
+        r#"
+        select candidate
+        from unnest($1::text[]) as candidate
+        where exists(select 1 from profile where username = candidate)
+          or username_recently_used(candidate, null)
+        "#,
+    )
+    .bind(&candidates)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't check usernames in DB: {err}"
+        )))
+    })?
+    .into_iter()
+    .map(|(username,)| username)
+    .collect();
+
+    Ok(UsernameAvailability {
+        problem,
+        suggestions: candidates
+            .into_iter()
+            .filter(|candidate| !taken.contains(candidate))
+            .take(MAX_SUGGESTIONS)
+            .collect(),
+    })
+}
@@ -2,6 +2,17 @@ mod components;
 #[cfg(feature = "ssr")]
 mod ssr;
 
+/// What the server binary in `main.rs` uses from the library.
+#[cfg(feature = "ssr")]
+pub mod server {
+    pub use crate::components::app::App;
+    pub use crate::ssr::account_deletion::spawn_purge_task;
+    pub use crate::ssr::app_state::{AppState, DEFAULT_MAX_LOGIN_CODE_ATTEMPTS};
+    pub use crate::ssr::csrf::{SiteOrigin, check_csrf};
+    pub use crate::ssr::oidc::Oidc;
+    pub use crate::ssr::request::TrustedProxies;
+}
+
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
 pub fn hydrate() {
@@ -1,12 +1,11 @@
+// The server binary uses the library instead, through `questarch::server`.
+#[cfg(not(feature = "ssr"))]
 mod components;
-#[cfg(feature = "ssr")]
-mod ssr;
 
 #[cfg(feature = "ssr")]
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    use crate::components::app::*;
-    use crate::ssr::app_state::AppState;
+    use questarch::server::*;
 
     use actix_files::Files;
     use actix_web::*;
@@ -49,18 +48,54 @@ async fn main() -> std::io::Result<()> {
         .expect("should be able to initialize mailer")
         .build();
 
+    // Used to build links in emails, so it can't come from the request's Host header.
+    let site_url = std::env::var("SITE_URL").expect("SITE_URL should be set");
+    let site_url = site_url.trim_end_matches('/').to_string();
+
+    // Passkeys are bound to the site's domain, so they also come from SITE_URL.
+    let site_origin =
+        webauthn_rs::prelude::Url::parse(&site_url).expect("SITE_URL should be a URL");
+    let webauthn = webauthn_rs::WebauthnBuilder::new(
+        site_origin.host_str().expect("SITE_URL should have a host"),
+        &site_origin,
+    )
+    .and_then(|builder| builder.rp_name("Questarch").build())
+    .expect("should be able to set up passkeys for SITE_URL");
+
+    // Requests that change anything have to come from the site's own pages.
+    let site_origin = site_origin.origin().ascii_serialization();
+
+    let max_login_code_attempts = std::env::var("MAX_LOGIN_CODE_ATTEMPTS")
+        .map(|attempts| {
+            attempts
+                .parse()
+                .expect("MAX_LOGIN_CODE_ATTEMPTS should be a number")
+        })
+        .unwrap_or(DEFAULT_MAX_LOGIN_CODE_ATTEMPTS);
+
     let app_state = AppState {
         db_pool,
         valkey_pool,
         mailer,
+        site_url,
+        webauthn: std::sync::Arc::new(webauthn),
+        oidc: std::sync::Arc::new(Oidc::from_env()),
+        max_login_code_attempts,
     };
 
+    spawn_purge_task(app_state.clone());
+
+    // Only believe X-Forwarded-For when it comes from one of these, since clients can send it too.
+    let trusted_proxies = web::Data::new(TrustedProxies::from_env());
+
     HttpServer::new(move || {
         // Generate the list of routes in your Leptos App
         let routes = generate_route_list(App);
         let leptos_options = &conf.leptos_options;
         let site_root = leptos_options.site_root.clone().to_string();
         let app_state = app_state.clone();
+        let site_origin = SiteOrigin(site_origin.clone());
+        let trusted_proxies = trusted_proxies.clone();
 
         App::new()
             // serve JS/WASM/CSS from `pkg`
@@ -100,6 +135,9 @@ async fn main() -> std::io::Result<()> {
                 },
             )
             .app_data(web::Data::new(leptos_options.to_owned()))
+            .app_data(web::Data::new(site_origin))
+            .app_data(trusted_proxies)
+            .wrap(middleware::from_fn(check_csrf))
             .wrap(middleware::Compress::default())
             .wrap(middleware::Logger::default())
     })
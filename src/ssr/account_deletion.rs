@@ -0,0 +1,145 @@
+/// Deleting accounts for good once the grace period after their owner asked for it is over.
+use crate::components::username::USERNAME_RESERVED_DAYS;
+use crate::ssr::app_state::AppState;
+use crate::ssr::key;
+use crate::ssr::uuid_codec::encode_uuid;
+
+use fred::interfaces::KeysInterface;
+use leptos::prelude::*;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long an owner has to change their mind after asking for their account to be deleted.
+pub const ACCOUNT_DELETION_GRACE_DAYS: i64 = 30;
+const PURGE_INTERVAL_SEC: u64 = 60 * 60;
+
+/// Periodically delete every account whose grace period is over, for as long as the server runs.
+pub fn spawn_purge_task(app_state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(PURGE_INTERVAL_SEC));
+        loop {
+            interval.tick().await;
+            match purge_deleted_accounts(&app_state).await {
+                Ok(0) => {}
+                Ok(purged) => log::info!("Deleted {purged} accounts after their grace period"),
+                Err(err) => log::warn!("Failed to delete accounts after their grace period: {err}"),
+            }
+            if let Err(err) = purge_released_usernames(&app_state).await {
+                log::warn!("Failed to forget usernames of deleted profiles: {err}");
+            }
+        }
+    });
+}
+
+/// Delete every account whose grace period is over. Returns how many were deleted.
+async fn purge_deleted_accounts(app_state: &AppState) -> Result<usize, ServerFnError> {
+    let account_ids = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        select id
+        from account
+        where deletion_requested_at < now() - make_interval(days => $1)
+        "#,
+    )
+    .bind(ACCOUNT_DELETION_GRACE_DAYS as i32)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to find accounts to delete: {err}"
+        )))
+    })?;
+
+    let mut purged = 0;
+    for account_id in account_ids {
+        if purge_account(app_state, account_id).await? {
+            purged += 1;
+        }
+    }
+    Ok(purged)
+}
+
+/// Forget the usernames of deleted profiles, including those of purged accounts, once they're no
+/// longer reserved. Renamed profiles keep theirs so links using them still redirect.
+async fn purge_released_usernames(app_state: &AppState) -> Result<(), ServerFnError> {
+    sqlx::query(
+        r#"
+        delete from profile_username_history
+        where profile_id is null
+          and changed_at < now() - make_interval(days => $1)
+        "#,
+    )
+    .bind(USERNAME_RESERVED_DAYS as i32)
+    .execute(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to delete username history: {err}"
+        )))
+    })?;
+    Ok(())
+}
+
+/// Delete an account, its profiles and anything else left of it. Returns false if the deletion
+/// was cancelled in the meantime.
+async fn purge_account(app_state: &AppState, account_id: Uuid) -> Result<bool, ServerFnError> {
+    let mut transaction = app_state.db_pool.begin().await?;
+
+    // Lock the account, and check again in case its owner cancelled the deletion just now.
+    let still_requested = sqlx::query_scalar::<_, bool>(
+        r#"
+        select coalesce(deletion_requested_at < now() - make_interval(days => $2), false)
+        from account
+        where id = $1
+        for update
+        "#,
+    )
+    .bind(account_id)
+    .bind(ACCOUNT_DELETION_GRACE_DAYS as i32)
+    .fetch_optional(&mut *transaction)
+    .await
+    .or_else(|err| Err(ServerFnError::new(format!("Failed to lock account: {err}"))))?
+    .unwrap_or(false);
+    if !still_requested {
+        return Ok(false);
+    }
+
+    sqlx::query("update account set default_profile = null where id = $1")
+        .bind(account_id)
+        .execute(&mut *transaction)
+        .await
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Failed to clear default profile: {err}"
+            )))
+        })?;
+    sqlx::query("delete from profile where account_id = $1")
+        .bind(account_id)
+        .execute(&mut *transaction)
+        .await
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Failed to delete profiles: {err}"
+            )))
+        })?;
+    sqlx::query("delete from account where id = $1")
+        .bind(account_id)
+        .execute(&mut *transaction)
+        .await
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Failed to delete account: {err}"
+            )))
+        })?;
+
+    transaction.commit().await?;
+
+    // Sessions were already ended when the deletion was requested, but the owner may have logged
+    // in again since.
+    app_state.end_all_sessions(account_id).await?;
+    app_state
+        .valkey_pool
+        .del::<(), _>(key::email_verification(&encode_uuid(account_id)))
+        .await?;
+
+    Ok(true)
+}
@@ -0,0 +1,76 @@
+/// Personal API tokens, for scripts and bots to act as a profile without a browser session.
+use actix_web::HttpRequest;
+use actix_web::http::header::AUTHORIZATION;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    thread_rng,
+};
+use sha2::{Digest, Sha256};
+
+/// Makes tokens easy to recognize, e.g. by secret scanners, if they leak.
+const TOKEN_PREFIX: &str = "qat_";
+/// Random characters after the prefix. Tokens are long lived, so they get more than session IDs.
+const TOKEN_SECRET_LEN: usize = 32;
+
+/// What a token is allowed to do as its profile. Browser sessions can do everything.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiScope {
+    /// See what the profile can see.
+    Read,
+    /// Post and make changes as the profile.
+    Write,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 2] = [ApiScope::Read, ApiScope::Write];
+
+    /// Name the scope is stored and shown as.
+    pub fn name(self) -> &'static str {
+        match self {
+            ApiScope::Read => "read",
+            ApiScope::Write => "write",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ApiScope> {
+        ApiScope::ALL.into_iter().find(|scope| scope.name() == name)
+    }
+}
+
+/// The token a request was made with, if it was made with one rather than a session cookie.
+#[derive(Clone, Debug)]
+pub struct ApiTokenAuth {
+    pub scopes: Vec<ApiScope>,
+}
+
+/// Make a new random token, returning it along with the hash to store.
+pub fn generate_token() -> (String, Vec<u8>) {
+    let token = format!(
+        "{TOKEN_PREFIX}{}",
+        Alphanumeric.sample_string(&mut thread_rng(), TOKEN_SECRET_LEN)
+    );
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+/// Tokens are random enough that a plain hash can't be brute forced, so no salt or slow hash is
+/// needed, and they can be looked up by hash.
+pub fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+/// Whether a token is in the right format, to skip the DB for obvious garbage.
+pub fn valid_token(token: &str) -> bool {
+    token.strip_prefix(TOKEN_PREFIX).is_some_and(|secret| {
+        secret.len() == TOKEN_SECRET_LEN && secret.chars().all(char::is_alphanumeric)
+    })
+}
+
+/// Get the token from the request's `Authorization: Bearer` header, if it has one.
+pub fn bearer_token(request: &HttpRequest) -> Option<&str> {
+    let value = request.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then_some(token.trim())
+}
@@ -1,5 +1,8 @@
-use crate::ssr::cookie::set_cookie;
+use crate::ssr::api_token::{ApiScope, ApiTokenAuth, bearer_token, hash_token, valid_token};
+use crate::ssr::cookie::{remove_cookie, set_cookie};
 use crate::ssr::key;
+use crate::ssr::oidc::Oidc;
+use crate::ssr::request::{client_ip, user_agent};
 use crate::ssr::uuid_codec::{decode_uuid, encode_uuid};
 
 use actix_web::HttpRequest;
@@ -7,7 +10,9 @@ use actix_web::cookie;
 use actix_web::cookie::Cookie;
 use fred::interfaces::HashesInterface;
 use fred::interfaces::KeysInterface;
+use fred::interfaces::SetsInterface;
 use fred::interfaces::TransactionInterface;
+use fred::types::ExpireOptions;
 use leptos::prelude::*;
 use leptos_actix::ResponseOptions;
 use lettre::{AsyncSmtpTransport, Tokio1Executor};
@@ -15,15 +20,24 @@ use rand::{
     distributions::{Alphanumeric, DistString},
     thread_rng,
 };
+use std::sync::Arc;
 use uuid::Uuid;
+use webauthn_rs::Webauthn;
 
 // See https://owasp.org/www-community/vulnerabilities/Insufficient_Session-ID_Length for
 // considerations for secret lengths.
 const SESSION_ID_LEN: usize = 16;
-const SESSION_ID_COOKIE: &str = "sess";
+pub const SESSION_ID_COOKIE: &str = "sess";
 const SESSION_TTL_DAYS: i64 = 180; // 30 days
 const SESSION_TTL_SEC: i64 = SESSION_TTL_DAYS * 24 * 60 * 60; // 180 days
 
+const SESSION_HANDLE_LEN: usize = 8;
+const LAST_SEEN_RESOLUTION_SEC: i64 = 60;
+
+/// Wrong answers allowed before a login code is invalidated, unless `MAX_LOGIN_CODE_ATTEMPTS` is
+/// set.
+pub const DEFAULT_MAX_LOGIN_CODE_ATTEMPTS: i64 = 5;
+
 /// Easily cloneable prototype.
 #[allow(dead_code)] // For prototyping
 #[derive(Clone)]
@@ -31,77 +45,401 @@ pub struct AppState {
     pub db_pool: sqlx::postgres::PgPool,
     pub valkey_pool: fred::clients::Pool,
     pub mailer: AsyncSmtpTransport<Tokio1Executor>,
+    /// Public URL of the site, without a trailing slash, e.g. `https://example.com`.
+    pub site_url: String,
+    /// Relying party for passkeys, tied to the site URL.
+    pub webauthn: Arc<Webauthn>,
+    /// OpenID Connect providers accounts can log in with.
+    pub oidc: Arc<Oidc>,
+    /// Wrong answers allowed before a login code or second factor challenge is invalidated.
+    pub max_login_code_attempts: i64,
+}
+
+/// Details about a session, for showing the user where they're logged in.
+pub struct SessionDetails {
+    pub session_id: String,
+    /// Public identifier for the session. Unlike the session ID, this is safe to show.
+    pub handle: String,
+    /// Unix timestamp of when the session was created.
+    pub created_at: i64,
+    /// Unix timestamp of when the session was last used, to within LAST_SEEN_RESOLUTION_SEC.
+    pub last_seen: i64,
+    pub user_agent: String,
+    pub ip: String,
 }
 
 /// Data associated with a session.
 pub struct SessionInfo {
     pub account_id: Uuid,
+    /// Empty for requests made with an API token.
     pub session_id: String,
     pub username: String,
     pub display_name: String,
+    /// The API token the request was made with, if it wasn't made with a session cookie.
+    pub api_token: Option<ApiTokenAuth>,
 }
 
 impl AppState {
-    /// Helper to get a user's session details.
+    /// Helper to get a user's session details. Requests from scripts can use an API token in an
+    /// `Authorization: Bearer` header instead of a session cookie, which then acts as a session
+    /// limited to the token's profile and scopes.
     pub async fn get_session(
         &self,
         request: HttpRequest,
     ) -> Option<Result<SessionInfo, ServerFnError>> {
-        if let Some(session_cookie) = request.cookie(SESSION_ID_COOKIE) {
+        if let Some(token) = bearer_token(&request) {
+            Some(self.get_token_session(token).await)
+        } else if let Some(session_cookie) = request.cookie(SESSION_ID_COOKIE) {
             Some(self.get_session_for(session_cookie.value()).await)
         } else {
             None
         }
     }
 
+    /// Helper to get a user's session details, failing if they aren't logged in. For server
+    /// functions that only logged in users can call. API tokens are refused, since these manage
+    /// the account itself.
+    pub async fn require_session(
+        &self,
+        request: HttpRequest,
+    ) -> Result<SessionInfo, ServerFnError> {
+        let session = self.require_scope(request, None).await?;
+        if session.api_token.is_some() {
+            return Err(ServerFnError::new(
+                "API tokens can't be used for this. Log in on the site instead.",
+            ));
+        }
+        Ok(session)
+    }
+
+    /// Helper to get a user's session details, failing if they aren't logged in, or if they used
+    /// an API token without the given scope. No scope accepts any token.
+    pub async fn require_scope(
+        &self,
+        request: HttpRequest,
+        scope: Option<ApiScope>,
+    ) -> Result<SessionInfo, ServerFnError> {
+        let session = self
+            .get_session(request)
+            .await
+            .unwrap_or_else(|| Err(ServerFnError::new("You need to log in first.")))?;
+        let missing_scope = scope.filter(|scope| {
+            session
+                .api_token
+                .as_ref()
+                .is_some_and(|api_token| !api_token.scopes.contains(scope))
+        });
+        if let Some(scope) = missing_scope {
+            return Err(ServerFnError::new(format!(
+                "This API token doesn't have the {} scope.",
+                scope.name()
+            )));
+        }
+        Ok(session)
+    }
+
+    /// Helper to get a user's session details, failing if they aren't logged in or are in reader
+    /// mode. For server functions that need a profile to act as, which API tokens with the given
+    /// scope can also call.
+    pub async fn require_profile(
+        &self,
+        request: HttpRequest,
+        scope: ApiScope,
+    ) -> Result<SessionInfo, ServerFnError> {
+        let session = self.require_scope(request, Some(scope)).await?;
+        if session.username.is_empty() {
+            return Err(ServerFnError::new(
+                "You need to pick a profile first. This can't be done in reader mode.",
+            ));
+        }
+        Ok(session)
+    }
+
     /// Helper to create a session for a new given user.
     pub async fn create_session(
         &self,
+        request: &HttpRequest,
         response_options: &ResponseOptions,
         account_id: Uuid,
         username: Option<String>,
         display_name: Option<String>,
     ) -> Result<(), ServerFnError> {
         let session_id = Alphanumeric.sample_string(&mut thread_rng(), SESSION_ID_LEN);
+        let sessions_key = key::account_sessions(&encode_uuid(account_id));
+        let now = unix_time().to_string();
+
+        let mut fields = vec![
+            ("acctid", encode_uuid(account_id)),
+            (
+                "handle",
+                Alphanumeric.sample_string(&mut thread_rng(), SESSION_HANDLE_LEN),
+            ),
+            ("ctime", now.clone()),
+            ("ltime", now),
+            ("ua", user_agent(request)),
+            ("ip", client_ip(request)),
+        ];
+        if let Some(username) = username {
+            fields.push(("uname", username));
+            fields.push(("dname", display_name.unwrap_or_default()));
+        }
 
         let transaction = self.valkey_pool.multi();
-        if username.is_some() {
+        let _ = transaction
+            .hset::<i64, _, _>(key::session(&session_id), fields)
+            .await;
+        let _ = transaction
+            .expire::<i64, _>(key::session(&session_id), SESSION_TTL_SEC, None)
+            .await;
+        // Index of the account's sessions, so they can all be ended at once. It lives as long as
+        // the newest session.
+        let _ = transaction
+            .sadd::<i64, _, _>(&sessions_key, &session_id)
+            .await;
+        let _ = transaction
+            .expire::<i64, _>(&sessions_key, SESSION_TTL_SEC, None)
+            .await;
+
+        transaction
+            .exec::<(i64, i64, i64, i64)>(true)
+            .await
+            .or_else(|err| {
+                Err(ServerFnError::new(format!(
+                    "Failed to create session: {err}"
+                )))
+            })?;
+
+        let session_cookie = Cookie::build(SESSION_ID_COOKIE, session_id)
+            .max_age(cookie::time::Duration::days(SESSION_TTL_DAYS))
+            .same_site(cookie::SameSite::Lax)
+            .path("/")
+            .http_only(true)
+            // .secure(true) // no dev HTTPS setup
+            .finish();
+        set_cookie(response_options, &session_cookie)?;
+
+        Ok(())
+    }
+
+    /// Helper to end the session of the given request, both on the server and in the client's
+    /// cookies.
+    pub async fn end_session(
+        &self,
+        request: HttpRequest,
+        response_options: &ResponseOptions,
+    ) -> Result<(), ServerFnError> {
+        if let Some(session_cookie) = request.cookie(SESSION_ID_COOKIE) {
+            let session_id = session_cookie.value();
+            if self.valid_session_id(session_id) {
+                let account_id = self
+                    .valkey_pool
+                    .hget::<Option<String>, _, _>(key::session(session_id), "acctid")
+                    .await
+                    .or_else(|err| {
+                        Err(ServerFnError::new(format!("Failed to get session: {err}")))
+                    })?;
+
+                let transaction = self.valkey_pool.multi();
+                let _ = transaction.del::<i64, _>(key::session(session_id)).await;
+                if let Some(account_id) = account_id {
+                    let _ = transaction
+                        .srem::<i64, _, _>(key::account_sessions(&account_id), session_id)
+                        .await;
+                }
+                transaction.exec::<()>(true).await.or_else(|err| {
+                    Err(ServerFnError::new(format!("Failed to end session: {err}")))
+                })?;
+            }
+        }
+
+        remove_cookie(response_options, SESSION_ID_COOKIE)?;
+
+        Ok(())
+    }
+
+    /// Helper to end every session of an account, e.g. in case one of them was compromised. The
+    /// caller's session cookie is left alone.
+    pub async fn end_all_sessions(&self, account_id: Uuid) -> Result<(), ServerFnError> {
+        let sessions_key = key::account_sessions(&encode_uuid(account_id));
+        let session_ids = self
+            .valkey_pool
+            .smembers::<Vec<String>, _>(&sessions_key)
+            .await
+            .or_else(|err| Err(ServerFnError::new(format!("Failed to get sessions: {err}"))))?;
+
+        let transaction = self.valkey_pool.multi();
+        for session_id in session_ids {
+            let _ = transaction.del::<i64, _>(key::session(&session_id)).await;
+        }
+        let _ = transaction.del::<i64, _>(&sessions_key).await;
+        transaction
+            .exec::<()>(true)
+            .await
+            .or_else(|err| Err(ServerFnError::new(format!("Failed to end sessions: {err}"))))?;
+
+        Ok(())
+    }
+
+    /// Helper to list all sessions of an account, most recently used first.
+    pub async fn list_sessions(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Vec<SessionDetails>, ServerFnError> {
+        let sessions_key = key::account_sessions(&encode_uuid(account_id));
+        let session_ids = self
+            .valkey_pool
+            .smembers::<Vec<String>, _>(&sessions_key)
+            .await
+            .or_else(|err| Err(ServerFnError::new(format!("Failed to get sessions: {err}"))))?;
+
+        let mut sessions = Vec::with_capacity(session_ids.len());
+        let mut expired = Vec::new();
+        for session_id in session_ids {
+            let [handle, created_at, last_seen, user_agent, ip] = self
+                .valkey_pool
+                .hmget::<[Option<String>; 5], _, _>(
+                    key::session(&session_id),
+                    ("handle", "ctime", "ltime", "ua", "ip"),
+                )
+                .await
+                .or_else(|err| Err(ServerFnError::new(format!("Failed to get session: {err}"))))?;
+
+            match handle {
+                Some(handle) => sessions.push(SessionDetails {
+                    session_id,
+                    handle,
+                    created_at: created_at.and_then(|t| t.parse().ok()).unwrap_or_default(),
+                    last_seen: last_seen.and_then(|t| t.parse().ok()).unwrap_or_default(),
+                    user_agent: user_agent.unwrap_or_default(),
+                    ip: ip.unwrap_or_default(),
+                }),
+                // The session expired, or predates session handles.
+                None => expired.push(session_id),
+            }
+        }
+
+        if !expired.is_empty() {
+            let valkey_pool = self.valkey_pool.clone();
+            tokio::spawn(async move {
+                if let Err(err) = valkey_pool.srem::<i64, _, _>(&sessions_key, expired).await {
+                    log::warn!("Ignored error clearing expired session index entries: {err}");
+                }
+            });
+        }
+
+        sessions.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+        Ok(sessions)
+    }
+
+    /// Helper to end one session of an account, identified by its public handle. Returns whether
+    /// there was such a session.
+    pub async fn end_session_by_handle(
+        &self,
+        account_id: Uuid,
+        handle: &str,
+    ) -> Result<bool, ServerFnError> {
+        let Some(session) = self
+            .list_sessions(account_id)
+            .await?
+            .into_iter()
+            .find(|session| session.handle == handle)
+        else {
+            return Ok(false);
+        };
+
+        let transaction = self.valkey_pool.multi();
+        let _ = transaction
+            .del::<i64, _>(key::session(&session.session_id))
+            .await;
+        let _ = transaction
+            .srem::<i64, _, _>(
+                key::account_sessions(&encode_uuid(account_id)),
+                &session.session_id,
+            )
+            .await;
+        transaction
+            .exec::<()>(true)
+            .await
+            .or_else(|err| Err(ServerFnError::new(format!("Failed to end session: {err}"))))?;
+
+        Ok(true)
+    }
+
+    /// Helper to switch which profile an existing session is acting as. No username means reader
+    /// mode.
+    pub async fn set_session_profile(
+        &self,
+        session_id: &str,
+        username: Option<String>,
+        display_name: Option<String>,
+    ) -> Result<(), ServerFnError> {
+        let transaction = self.valkey_pool.multi();
+        if let Some(username) = username {
             let _ = transaction
                 .hset::<i64, _, _>(
-                    key::session(&session_id),
+                    key::session(session_id),
                     [
-                        ("acctid", encode_uuid(account_id)),
-                        ("uname", username.unwrap_or_default()),
+                        ("uname", username),
                         ("dname", display_name.unwrap_or_default()),
                     ],
                 )
                 .await;
         } else {
             let _ = transaction
-                .hset::<i64, _, _>(
-                    key::session(&session_id),
-                    ("acctid", encode_uuid(account_id)),
-                )
+                .hdel::<i64, _, _>(key::session(session_id), ("uname", "dname"))
                 .await;
         }
+        // If the session expired in the meantime, the hset above recreates it without an
+        // account_id, which get_session_for will clean up. This just makes sure such a leftover
+        // can't live forever.
         let _ = transaction
-            .expire::<i64, _>(key::session(&session_id), SESSION_TTL_SEC, None)
+            .expire::<i64, _>(
+                key::session(session_id),
+                SESSION_TTL_SEC,
+                Some(ExpireOptions::NX),
+            )
             .await;
 
         transaction.exec::<(i64, i64)>(true).await.or_else(|err| {
             Err(ServerFnError::new(format!(
-                "Failed to create session: {err}"
+                "Failed to switch session profile: {err}"
             )))
         })?;
 
-        let session_cookie = Cookie::build("sess", session_id)
-            .max_age(cookie::time::Duration::days(SESSION_TTL_DAYS))
-            .same_site(cookie::SameSite::Lax)
-            .path("/")
-            .http_only(true)
-            // .secure(true) // no dev HTTPS setup
-            .finish();
-        set_cookie(response_options, &session_cookie)?;
+        Ok(())
+    }
+
+    /// Helper to update every session of an account that's acting as a profile after the profile
+    /// was renamed, or to switch them to reader mode after it was deleted.
+    pub async fn replace_session_profile(
+        &self,
+        account_id: Uuid,
+        old_username: &str,
+        new_username: Option<&str>,
+    ) -> Result<(), ServerFnError> {
+        let session_ids = self
+            .valkey_pool
+            .smembers::<Vec<String>, _>(key::account_sessions(&encode_uuid(account_id)))
+            .await
+            .or_else(|err| Err(ServerFnError::new(format!("Failed to get sessions: {err}"))))?;
+
+        for session_id in session_ids {
+            let [username, display_name] = self
+                .valkey_pool
+                .hmget::<[Option<String>; 2], _, _>(key::session(&session_id), ("uname", "dname"))
+                .await
+                .or_else(|err| Err(ServerFnError::new(format!("Failed to get session: {err}"))))?;
+
+            if username.as_deref() == Some(old_username) {
+                let display_name = new_username.and(display_name);
+                self.set_session_profile(
+                    &session_id,
+                    new_username.map(str::to_string),
+                    display_name,
+                )
+                .await?;
+            }
+        }
 
         Ok(())
     }
@@ -121,6 +459,33 @@ impl AppState {
         });
     }
 
+    /// Helper function for recording that a session was just used.
+    fn background_touch_session(&self, session_id: &str) {
+        let session_id = session_id.to_string();
+        let valkey_pool = self.valkey_pool.clone();
+        tokio::spawn(async move {
+            let transaction = valkey_pool.multi();
+            let _ = transaction
+                .hset::<i64, _, _>(
+                    key::session(&session_id),
+                    ("ltime", unix_time().to_string()),
+                )
+                .await;
+            // Like in set_session_profile, a session that expired in the meantime gets recreated
+            // without an account_id by the hset, so don't let that leftover live forever.
+            let _ = transaction
+                .expire::<i64, _>(
+                    key::session(&session_id),
+                    SESSION_TTL_SEC,
+                    Some(ExpireOptions::NX),
+                )
+                .await;
+            if let Err(err) = transaction.exec::<(i64, i64)>(true).await {
+                log::warn!("Ignored error updating session last seen time: {err}");
+            }
+        });
+    }
+
     /// Helper to check if a session ID is in the right format.
     fn valid_session_id(&self, session_id: &str) -> bool {
         session_id.len() == SESSION_ID_LEN && session_id.chars().all(char::is_alphanumeric)
@@ -134,15 +499,17 @@ impl AppState {
             ));
         }
 
-        let [account_id, username, display_name] = self
+        // Fields may be missing (e.g. no username in reader mode), so they have to be read as
+        // Options first.
+        let [account_id, username, display_name, last_seen] = self
             .valkey_pool
-            .hmget::<[String; 3], _, _>(key::session(session_id), ("acctid", "uname", "dname"))
+            .hmget::<[Option<String>; 4], _, _>(
+                key::session(session_id),
+                ("acctid", "uname", "dname", "ltime"),
+            )
             .await
-            .or_else(|err| {
-                Err(ServerFnError::new(format!(
-                    "Failed to commit account creation: {err}"
-                )))
-            })?;
+            .or_else(|err| Err(ServerFnError::new(format!("Failed to get session: {err}"))))?
+            .map(Option::unwrap_or_default);
 
         if account_id.is_empty() {
             if !username.is_empty() || !display_name.is_empty() {
@@ -154,7 +521,7 @@ impl AppState {
             ));
         }
 
-        let account_id = match Uuid::try_parse(&account_id) {
+        let account_id = match decode_uuid(&account_id) {
             Ok(account_id) => account_id,
             Err(err) => {
                 log::error!("Unparseable account_id \"{}\": {}", account_id, err);
@@ -165,13 +532,110 @@ impl AppState {
             }
         };
 
+        // Only write the last seen time every so often, rather than on every request.
+        if !last_seen
+            .parse::<i64>()
+            .is_ok_and(|last_seen| unix_time() - last_seen < LAST_SEEN_RESOLUTION_SEC)
+        {
+            self.background_touch_session(session_id);
+        }
+
         Ok(SessionInfo {
             account_id,
             session_id: session_id.to_string(),
             username,
             display_name,
+            api_token: None,
+        })
+    }
+
+    /// Private helper for getting the session an API token acts as.
+    async fn get_token_session(&self, token: &str) -> Result<SessionInfo, ServerFnError> {
+        let invalid = || ServerFnError::new("Invalid or revoked API token.");
+        if !valid_token(token) {
+            return Err(invalid());
+        }
+
+        let (id, account_id, username, display_name, scopes, stale) =
+            sqlx::query_as::<_, (Uuid, Uuid, String, Option<String>, Vec<String>, bool)>(
+                r#"
+                select
+                  api_token.id,
+                  api_token.account_id,
+                  profile.username::text,
+                  profile.display_name,
+                  api_token.scopes,
+                  coalesce(
+                    api_token.last_used_at < now() - make_interval(secs => $2),
+                    true
+                  )
+                from
+                  api_token
+                  join profile on api_token.profile_id = profile.id
+                  join account on api_token.account_id = account.id
+                where api_token.token_hash = $1
+                  -- Like sessions, tokens stop working once the account is being deleted, but
+                  -- they work again if the deletion is cancelled.
+                  and account.deletion_requested_at is null
+                "#,
+            )
+            .bind(hash_token(token))
+            .bind(LAST_SEEN_RESOLUTION_SEC as f64)
+            .fetch_optional(&self.db_pool)
+            .await
+            .or_else(|err| {
+                Err(ServerFnError::new(format!(
+                    "Failed to get API token: {err}"
+                )))
+            })?
+            .ok_or_else(invalid)?;
+
+        // Only write the last used time every so often, rather than on every request.
+        if stale {
+            self.background_touch_api_token(id);
+        }
+
+        Ok(SessionInfo {
+            account_id,
+            session_id: String::new(),
+            username,
+            display_name: display_name.unwrap_or_default(),
+            api_token: Some(ApiTokenAuth {
+                scopes: scopes
+                    .iter()
+                    .filter_map(|scope| ApiScope::from_name(scope))
+                    .collect(),
+            }),
         })
     }
+
+    /// Helper function for recording that an API token was just used.
+    fn background_touch_api_token(&self, id: Uuid) {
+        let db_pool = self.db_pool.clone();
+        tokio::spawn(async move {
+            if let Err(err) = sqlx::query(
+                r#"
+                update api_token
+                set last_used_at = now()
+                where id = $1
+                "#,
+            )
+            .bind(id)
+            .execute(&db_pool)
+            .await
+            {
+                log::warn!("Ignored error updating API token last used time: {err}");
+            }
+        });
+    }
+}
+
+/// Current Unix timestamp in seconds.
+pub fn unix_time() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
 }
 
 /// Wrapper to get AppState that's easily usable with the ? operator, for use in
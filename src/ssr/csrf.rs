@@ -0,0 +1,186 @@
+/// Middleware protecting against cross-site request forgery. Requests that can change anything
+/// have to come from the site itself, as told by their Origin or Referer header, and have to
+/// carry the token from the CSRF cookie. Browsers only send cookies along with other sites'
+/// requests, and don't let them read the cookie, so a forged request fails both checks.
+use crate::components::csrf::{CSRF_COOKIE, CSRF_FIELD, CSRF_HEADER};
+use crate::ssr::api_token::bearer_token;
+use crate::ssr::app_state::SESSION_ID_COOKIE;
+
+use actix_web::body::MessageBody;
+use actix_web::cookie;
+use actix_web::cookie::Cookie;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::{CONTENT_TYPE, ORIGIN, REFERER};
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpResponse, web};
+use leptos::prelude::*;
+use leptos::server_fn::error::ServerFnErrorSerde;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    thread_rng,
+};
+
+const CSRF_TOKEN_LEN: usize = 32;
+/// Longer than sessions last, so open pages don't lose their token while still logged in.
+const CSRF_COOKIE_TTL_DAYS: i64 = 365;
+
+/// The CSRF token for the current request, available in its extensions, so pages rendered for a
+/// browser that didn't have one yet can use the one it's about to get.
+#[derive(Clone)]
+pub struct CsrfToken(pub String);
+
+/// Origin of the site, e.g. `https://example.com`, that requests have to come from.
+pub struct SiteOrigin(pub String);
+
+/// Check a request for signs of forgery, handing out a CSRF token to browsers without one.
+pub async fn check_csrf(
+    site_origin: web::Data<SiteOrigin>,
+    mut request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let existing_token = request
+        .cookie(CSRF_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|token| valid_token(token));
+    let new_token = existing_token
+        .is_none()
+        .then(|| Alphanumeric.sample_string(&mut thread_rng(), CSRF_TOKEN_LEN));
+    let token = existing_token
+        .clone()
+        .or_else(|| new_token.clone())
+        .unwrap_or_default();
+    request.extensions_mut().insert(CsrfToken(token));
+
+    let rejection = if needs_check(&request) {
+        match check_origin(&request, &site_origin.0) {
+            Err(reason) => Some(reason),
+            Ok(()) => check_token(&mut request, existing_token.as_deref())
+                .await
+                .err(),
+        }
+    } else {
+        None
+    };
+
+    let mut response = match rejection {
+        Some(reason) => {
+            log::warn!(
+                "Refused possibly forged {} request to {}: {reason}",
+                request.method(),
+                request.path()
+            );
+            // Written the way server functions send errors, so the page shows it like any other.
+            let body = ServerFnError::new(reason).ser().unwrap_or_default();
+            request
+                .into_response(HttpResponse::Forbidden().body(body))
+                .map_into_boxed_body()
+        }
+        None => next.call(request).await?.map_into_boxed_body(),
+    };
+
+    if let Some(new_token) = new_token {
+        // Readable by the site's own scripts, which have to send it back in a header.
+        let csrf_cookie = Cookie::build(CSRF_COOKIE, new_token)
+            .max_age(cookie::time::Duration::days(CSRF_COOKIE_TTL_DAYS))
+            .same_site(cookie::SameSite::Lax)
+            .path("/")
+            // .secure(true) // No dev https setup.
+            .finish();
+        response.response_mut().add_cookie(&csrf_cookie)?;
+    }
+
+    Ok(response)
+}
+
+/// Whether a token is in the right format, since cookies can be set to anything.
+fn valid_token(token: &str) -> bool {
+    token.len() == CSRF_TOKEN_LEN && token.chars().all(char::is_alphanumeric)
+}
+
+/// Reading requests can't change anything, and requests with only an API token can't be forged,
+/// since browsers never add that on their own.
+fn needs_check(request: &ServiceRequest) -> bool {
+    let safe_method = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    let token_only =
+        bearer_token(request.request()).is_some() && request.cookie(SESSION_ID_COOKIE).is_none();
+    !safe_method && !token_only
+}
+
+/// Check that the request came from one of the site's own pages.
+fn check_origin(request: &ServiceRequest, site_origin: &str) -> Result<(), &'static str> {
+    let header = |name| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+
+    // Browsers send Origin with every POST, but Referer is the fallback for old ones.
+    let same_site = match (header(ORIGIN), header(REFERER)) {
+        (Some(origin), _) => origin == site_origin,
+        (None, Some(referer)) => referer
+            .strip_prefix(site_origin)
+            .is_some_and(|path| path.is_empty() || path.starts_with('/')),
+        (None, None) => false,
+    };
+
+    if same_site {
+        Ok(())
+    } else {
+        Err("This request didn't come from this site, so it was blocked to protect your account.")
+    }
+}
+
+/// Check that the request carries the browser's CSRF token, in the header or, for forms
+/// submitted before the page hydrated, in a form field.
+async fn check_token(
+    request: &mut ServiceRequest,
+    expected: Option<&str>,
+) -> Result<(), &'static str> {
+    let expired =
+        "This page's security token is missing or expired. Reload the page and try again.";
+    let Some(expected) = expected else {
+        return Err(expired);
+    };
+
+    let header_token = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let token = match header_token {
+        Some(token) => Some(token),
+        None => form_token(request).await,
+    };
+
+    if token.as_deref() == Some(expected) {
+        Ok(())
+    } else {
+        Err(expired)
+    }
+}
+
+/// Get the token from a URL encoded form body, putting the body back for the handler.
+async fn form_token(request: &mut ServiceRequest) -> Option<String> {
+    let is_form = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    if !is_form {
+        return None;
+    }
+
+    let body = request.extract::<web::Bytes>().await.ok()?;
+    request.set_payload(Payload::from(body.clone()));
+
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body)
+        .ok()?
+        .into_iter()
+        .find(|(name, _)| name == CSRF_FIELD)
+        .map(|(_, value)| value)
+}
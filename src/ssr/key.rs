@@ -2,10 +2,54 @@ pub fn email_auth_code(secret: &str) -> String {
     format!("emauthsec:{secret}")
 }
 
+pub fn email_login_link(token: &str) -> String {
+    format!("emlink:{token}")
+}
+
+pub fn email_auth_resend(secret: &str) -> String {
+    format!("emresend:{secret}")
+}
+
 pub fn session(session_id: &str) -> String {
     format!("sess:{session_id}")
 }
 
+pub fn account_sessions(account_id: &str) -> String {
+    format!("acctsess:{account_id}")
+}
+
+pub fn email_verification(account_id: &str) -> String {
+    format!("emverify:{account_id}")
+}
+
+pub fn email_change_undo(token: &str) -> String {
+    format!("emundo:{token}")
+}
+
+pub fn rate_limit(name: &str, subject: &str) -> String {
+    format!("ratelim:{name}:{subject}")
+}
+
 pub fn new_registration(secret: &str) -> String {
     format!("regnew:{secret}")
 }
+
+pub fn passkey_registration(account_id: &str) -> String {
+    format!("pkreg:{account_id}")
+}
+
+pub fn passkey_login(challenge_id: &str) -> String {
+    format!("pklogin:{challenge_id}")
+}
+
+pub fn totp_enrolment(account_id: &str) -> String {
+    format!("totpnew:{account_id}")
+}
+
+pub fn second_factor(token: &str) -> String {
+    format!("2fa:{token}")
+}
+
+pub fn oidc_flow(state: &str) -> String {
+    format!("oidc:{state}")
+}
@@ -0,0 +1,122 @@
+/// Finishing a login, whichever way the user proved who they are.
+use crate::ssr::app_state::AppState;
+use crate::ssr::cookie::set_cookie;
+use crate::ssr::key;
+use crate::ssr::totp::has_second_factor;
+use crate::ssr::uuid_codec::encode_uuid;
+
+use actix_web::HttpRequest;
+use actix_web::cookie;
+use actix_web::cookie::Cookie;
+use fred::prelude::{HashesInterface, KeysInterface, TransactionInterface};
+use leptos::prelude::*;
+use leptos_actix::ResponseOptions;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    thread_rng,
+};
+use uuid::Uuid;
+
+/// Length of the token for a login that's waiting for its second factor.
+pub const SECOND_FACTOR_TOKEN_LEN: usize = 32;
+/// How long the second factor can take, the same as a login code.
+pub const SECOND_FACTOR_EXPIRATION_MIN: i64 = 20;
+
+/// Log in to an account that was proven some way other than a passkey, first asking for its
+/// second factor if it has one. Passkeys are already a second factor on their own.
+pub async fn log_in_or_ask_second_factor(
+    app_state: &AppState,
+    request: &HttpRequest,
+    response_options: &ResponseOptions,
+    account_id: Uuid,
+) -> Result<(), ServerFnError> {
+    if has_second_factor(app_state, account_id).await? {
+        start_second_factor(app_state, response_options, account_id).await?;
+        leptos_actix::redirect("/auth/totp");
+        Ok(())
+    } else {
+        log_in_account(app_state, request, response_options, account_id).await
+    }
+}
+
+/// Hold off on logging in to an account until its second factor is checked. The
+/// half-authenticated state expires like a login challenge, and only this browser can finish it.
+async fn start_second_factor(
+    app_state: &AppState,
+    response_options: &ResponseOptions,
+    account_id: Uuid,
+) -> Result<(), ServerFnError> {
+    let token = Alphanumeric.sample_string(&mut thread_rng(), SECOND_FACTOR_TOKEN_LEN);
+    let key = key::second_factor(&token);
+
+    let tx = app_state.valkey_pool.multi();
+    let _: () = tx
+        .hset(&key, [("account", encode_uuid(account_id))])
+        .await?;
+    let _: () = tx
+        .expire(&key, SECOND_FACTOR_EXPIRATION_MIN * 60, None)
+        .await?;
+    let _: () = tx.exec(false).await?;
+
+    // Half of a login, so it's kept from scripts like a session token.
+    let second_factor_cookie = Cookie::build("tfchal", token)
+        .max_age(cookie::time::Duration::minutes(
+            SECOND_FACTOR_EXPIRATION_MIN,
+        ))
+        .same_site(cookie::SameSite::Lax)
+        .path("/")
+        .http_only(true)
+        // .secure(true) // No dev https setup.
+        .finish();
+    set_cookie(response_options, &second_factor_cookie)
+}
+
+/// Log in to an account as its default profile, and redirect to where the user should go next.
+pub async fn log_in_account(
+    app_state: &AppState,
+    request: &HttpRequest,
+    response_options: &ResponseOptions,
+    account_id: Uuid,
+) -> Result<(), ServerFnError> {
+    let (ask_for_profile_on_login, username, display_name) =
+        sqlx::query_as::<_, (bool, Option<String>, Option<String>)>(
+            r#"
+            select
+              ask_for_profile_on_login,
+              profile.username,
+              profile.display_name
+            from
+              account
+              left join profile on account.default_profile = profile.id
+            where account.id = $1
+            "#,
+        )
+        .bind(account_id)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Couldn't get account from DB: {err}"
+            )))
+        })?;
+
+    app_state
+        .create_session(
+            request,
+            response_options,
+            account_id,
+            username,
+            display_name,
+        )
+        .await?;
+
+    // The session starts out as the default profile either way, so the picker is only a chance to
+    // switch away from it.
+    if ask_for_profile_on_login {
+        leptos_actix::redirect("/auth/profile");
+    } else {
+        leptos_actix::redirect("/");
+    }
+
+    Ok(())
+}
@@ -7,7 +7,12 @@ use lettre::{
     message::{MultiPart, SinglePart, header},
 };
 
-pub fn login_code(email_address: Address, code: &str, minutes: i64) -> Result<Message, Error> {
+pub fn login_code(
+    email_address: Address,
+    code: &str,
+    link: &str,
+    minutes: i64,
+) -> Result<Message, Error> {
     // Create the html we want to send.
     let html = view! {
         <head>
@@ -28,6 +33,7 @@ pub fn login_code(email_address: Address, code: &str, minutes: i64) -> Result<Me
                 "This code will expire in " {minutes}
                 " minutes. Please go back to the page you requested it from and enter it there. If you did not request this login code, you can ignore it."
             </p>
+            <p>"Or log in by opening this link, in any browser: " <a href=link>{link}</a></p>
             <p>"Goodbye."</p>
         </div>
     }.to_html();
@@ -41,7 +47,9 @@ This is an email login code for <site>.
 
 {code}
 
-Please go back to the page you requested it from and enter it there. If you did not request this login code, you can ignore it.
+This code will expire in {minutes} minutes. Please go back to the page you requested it from and enter it there. If you did not request this login code, you can ignore it.
+
+Or log in by opening this link, in any browser: {link}
 
 Goodbye.
 "#
@@ -65,3 +73,186 @@ Goodbye.
                 ),
         )
 }
+
+pub fn verification_code(
+    email_address: Address,
+    code: &str,
+    minutes: i64,
+) -> Result<Message, Error> {
+    let html = view! {
+        <head>
+            <title>"Email verification code"</title>
+            <style type="text/css">
+                "* { font-family: Arial, Helvetica, sans-serif; }"
+                ".container { display: flex; flex-direction: column; }" ".bigcode {"
+                "align-self: center;" "font-family: Courier New, monospace;" "font-size: 200%;"
+                "font-weight: bold;" "letter-spacing: 0.2rem;" "margin: 0.2rem auto;" "}"
+            </style>
+        </head>
+        <div class="container">
+            <h2>"Email verification code"</h2>
+            <p>"Hello,"</p>
+            <p>"Someone asked to use this email address for their account on <site>."</p>
+            <p class="bigcode">{code}</p>
+            <p>
+                "This code will expire in " {minutes}
+                " minutes. If it was you, go back to your account settings and enter it there. If not, you can ignore this email, and the address won't be used."
+            </p>
+            <p>"Goodbye."</p>
+        </div>
+    }.to_html();
+
+    let plain_text = format!(
+        r#"Email verification code
+
+Hello,
+Someone asked to use this email address for their account on <site>.
+
+{code}
+
+This code will expire in {minutes} minutes. If it was you, go back to your account settings and enter it there. If not, you can ignore this email, and the address won't be used.
+
+Goodbye.
+"#
+    );
+
+    Message::builder()
+        .from("No Reply <noreply@example.com>".parse().unwrap())
+        .to(Mailbox::new(None, email_address))
+        .subject("Email verification code")
+        .multipart(
+            MultiPart::alternative()
+                .singlepart(
+                    SinglePart::builder()
+                        .header(header::ContentType::TEXT_PLAIN)
+                        .body(plain_text),
+                )
+                .singlepart(
+                    SinglePart::builder()
+                        .header(header::ContentType::TEXT_HTML)
+                        .body(html),
+                ),
+        )
+}
+
+pub fn primary_email_changed(
+    email_address: Address,
+    new_email: &str,
+    undo_link: &str,
+    days: i64,
+) -> Result<Message, Error> {
+    let html = view! {
+        <head>
+            <title>"Your email was changed"</title>
+            <style type="text/css">"* { font-family: Arial, Helvetica, sans-serif; }"</style>
+        </head>
+        <div>
+            <h2>"Your email was changed"</h2>
+            <p>"Hello,"</p>
+            <p>
+                "The primary email of your account on <site> was changed from this address to "
+                {new_email.to_string()} "."
+            </p>
+            <p>
+                "If you didn't do this, someone else may be using your account. Within " {days}
+                " days, you can undo the change and log out every session of your account by opening this link: "
+                <a href=undo_link.to_string()>{undo_link.to_string()}</a>
+            </p>
+            <p>"If it was you, you can ignore this email."</p>
+            <p>"Goodbye."</p>
+        </div>
+    }.to_html();
+
+    let plain_text = format!(
+        r#"Your email was changed
+
+Hello,
+The primary email of your account on <site> was changed from this address to {new_email}.
+
+If you didn't do this, someone else may be using your account. Within {days} days, you can undo the change and log out every session of your account by opening this link: {undo_link}
+
+If it was you, you can ignore this email.
+
+Goodbye.
+"#
+    );
+
+    Message::builder()
+        .from("No Reply <noreply@example.com>".parse().unwrap())
+        .to(Mailbox::new(None, email_address))
+        .subject("Your email was changed")
+        .multipart(
+            MultiPart::alternative()
+                .singlepart(
+                    SinglePart::builder()
+                        .header(header::ContentType::TEXT_PLAIN)
+                        .body(plain_text),
+                )
+                .singlepart(
+                    SinglePart::builder()
+                        .header(header::ContentType::TEXT_HTML)
+                        .body(html),
+                ),
+        )
+}
+
+pub fn secondary_email_added(
+    email_address: Address,
+    new_email: &str,
+    undo_link: &str,
+    days: i64,
+) -> Result<Message, Error> {
+    let html = view! {
+        <head>
+            <title>"An email was added to your account"</title>
+            <style type="text/css">"* { font-family: Arial, Helvetica, sans-serif; }"</style>
+        </head>
+        <div>
+            <h2>"An email was added to your account"</h2>
+            <p>"Hello,"</p>
+            <p>
+                {new_email.to_string()}
+                " was added to your account on <site>, and can now be used to log in to it."
+            </p>
+            <p>
+                "If you didn't do this, someone else may be using your account. Within " {days}
+                " days, you can remove it and log out every session of your account by opening this link: "
+                <a href=undo_link.to_string()>{undo_link.to_string()}</a>
+            </p>
+            <p>"If it was you, you can ignore this email."</p>
+            <p>"Goodbye."</p>
+        </div>
+    }.to_html();
+
+    let plain_text = format!(
+        r#"An email was added to your account
+
+Hello,
+{new_email} was added to your account on <site>, and can now be used to log in to it.
+
+If you didn't do this, someone else may be using your account. Within {days} days, you can remove it and log out every session of your account by opening this link: {undo_link}
+
+If it was you, you can ignore this email.
+
+Goodbye.
+"#
+    );
+
+    Message::builder()
+        .from("No Reply <noreply@example.com>".parse().unwrap())
+        .to(Mailbox::new(None, email_address))
+        .subject("An email was added to your account")
+        .multipart(
+            MultiPart::alternative()
+                .singlepart(
+                    SinglePart::builder()
+                        .header(header::ContentType::TEXT_PLAIN)
+                        .body(plain_text),
+                )
+                .singlepart(
+                    SinglePart::builder()
+                        .header(header::ContentType::TEXT_HTML)
+                        .body(html),
+                ),
+        )
+}
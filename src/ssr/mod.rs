@@ -1,5 +1,14 @@
+pub mod account_deletion;
+pub mod api_token;
 pub mod app_state;
 pub mod cookie;
+pub mod csrf;
 pub mod key;
+pub mod login;
 pub mod mail;
+pub mod oidc;
+pub mod rate_limit;
+pub mod request;
+pub mod totp;
+pub mod username;
 pub mod uuid_codec;
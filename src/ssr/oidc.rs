@@ -0,0 +1,269 @@
+/// Logging in with OpenID Connect providers, and linking their accounts to ours.
+use crate::ssr::app_state::AppState;
+use crate::ssr::cookie::{get_cookie, remove_cookie, set_cookie};
+use crate::ssr::key;
+
+use actix_web::HttpRequest;
+use actix_web::cookie;
+use actix_web::cookie::Cookie;
+use fred::prelude::KeysInterface;
+use leptos::prelude::*;
+use leptos_actix::ResponseOptions;
+use openidconnect::core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata};
+use openidconnect::{
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet, EndpointNotSet,
+    EndpointSet, IssuerUrl, Nonce, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope,
+    TokenResponse, reqwest,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How long the user has to log in at the provider and come back.
+const OIDC_FLOW_EXPIRATION_MIN: i64 = 10;
+const STATE_COOKIE: &str = "oidcst";
+/// Where providers send users back to, relative to the site URL.
+const CALLBACK_PATH: &str = "/auth/oidc/callback";
+
+/// A configured OpenID Connect provider.
+#[derive(Clone, Debug)]
+pub struct OidcProvider {
+    /// Short name used in URLs and stored with linked identities, e.g. `gitlab`.
+    pub id: String,
+    /// Name shown to users, e.g. `GitLab`.
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+}
+
+/// Every configured provider, and the HTTP client to talk to them with.
+pub struct Oidc {
+    pub providers: Vec<OidcProvider>,
+    http_client: reqwest::Client,
+}
+
+/// Client for a provider as set up from its discovery document.
+type DiscoveredClient = CoreClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+/// A login or link in progress at a provider, kept in Valkey under its state parameter.
+#[derive(Deserialize, Serialize)]
+pub struct OidcFlow {
+    pub provider: String,
+    /// Account to link the identity to. None to log in with it instead.
+    pub link_to: Option<Uuid>,
+    pkce_verifier: String,
+    nonce: String,
+}
+
+/// An identity the provider vouched for.
+pub struct VerifiedIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+}
+
+impl Oidc {
+    /// Read providers from the environment. `OIDC_PROVIDERS` is a comma separated list of IDs, and
+    /// each ID, e.g. `gitlab`, is configured with `OIDC_GITLAB_NAME`, `OIDC_GITLAB_ISSUER`,
+    /// `OIDC_GITLAB_CLIENT_ID`, and optionally `OIDC_GITLAB_CLIENT_SECRET`.
+    pub fn from_env() -> Self {
+        let providers = std::env::var("OIDC_PROVIDERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                let var =
+                    |name: &str| std::env::var(format!("OIDC_{}_{name}", id.to_uppercase())).ok();
+                let required = |name: &str| {
+                    var(name).unwrap_or_else(|| {
+                        panic!("OIDC_{}_{name} should be set", id.to_uppercase())
+                    })
+                };
+                OidcProvider {
+                    id: id.to_lowercase(),
+                    name: var("NAME").unwrap_or_else(|| id.to_string()),
+                    issuer: required("ISSUER"),
+                    client_id: required("CLIENT_ID"),
+                    client_secret: var("CLIENT_SECRET"),
+                }
+            })
+            .collect();
+
+        let http_client = reqwest::ClientBuilder::new()
+            // Following redirects would let a provider make us request arbitrary URLs.
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("should be able to build OIDC HTTP client");
+
+        Self {
+            providers,
+            http_client,
+        }
+    }
+
+    pub fn provider(&self, id: &str) -> Result<&OidcProvider, ServerFnError> {
+        self.providers
+            .iter()
+            .find(|provider| provider.id == id)
+            .ok_or_else(|| ServerFnError::new("That login provider doesn't exist."))
+    }
+
+    /// Set up a client from the provider's discovery document. It's fetched every time so the
+    /// provider's signing keys are always current.
+    async fn client(
+        &self,
+        provider: &OidcProvider,
+        site_url: &str,
+    ) -> Result<DiscoveredClient, ServerFnError> {
+        let issuer = IssuerUrl::new(provider.issuer.clone()).or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "Invalid issuer for {}: {err}",
+                provider.name
+            )))
+        })?;
+        let metadata = CoreProviderMetadata::discover_async(issuer, &self.http_client)
+            .await
+            .or_else(|err| {
+                Err(ServerFnError::new(format!(
+                    "Couldn't reach {}: {err}",
+                    provider.name
+                )))
+            })?;
+        let redirect_url = RedirectUrl::new(format!("{site_url}{CALLBACK_PATH}"))
+            .or_else(|err| Err(ServerFnError::new(format!("Invalid redirect URL: {err}"))))?;
+
+        Ok(CoreClient::from_provider_metadata(
+            metadata,
+            ClientId::new(provider.client_id.clone()),
+            provider.client_secret.clone().map(ClientSecret::new),
+        )
+        .set_redirect_uri(redirect_url))
+    }
+}
+
+/// Send the user to a provider to log in, or to link their identity there to `link_to`. The
+/// flow is tied to this browser by a cookie, so a provider response can't be planted in
+/// someone else's.
+pub async fn start_oidc_flow(
+    app_state: &AppState,
+    response_options: &ResponseOptions,
+    provider: &str,
+    link_to: Option<Uuid>,
+) -> Result<(), ServerFnError> {
+    let provider = app_state.oidc.provider(provider)?;
+    let client = app_state.oidc.client(provider, &app_state.site_url).await?;
+
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let (url, state, nonce) = client
+        .authorize_url(
+            CoreAuthenticationFlow::AuthorizationCode,
+            CsrfToken::new_random,
+            Nonce::new_random,
+        )
+        .add_scope(Scope::new(String::from("email")))
+        .set_pkce_challenge(pkce_challenge)
+        .url();
+
+    let flow = serde_json::to_string(&OidcFlow {
+        provider: provider.id.clone(),
+        link_to,
+        pkce_verifier: pkce_verifier.into_secret(),
+        nonce: nonce.secret().clone(),
+    })
+    .or_else(|err| Err(ServerFnError::new(format!("Failed to save login: {err}"))))?;
+    app_state
+        .valkey_pool
+        .set::<(), _, _>(
+            key::oidc_flow(state.secret()),
+            flow,
+            Some(fred::types::Expiration::EX(OIDC_FLOW_EXPIRATION_MIN * 60)),
+            None,
+            false,
+        )
+        .await?;
+
+    // Lax, since the provider sends the user back with a top-level navigation.
+    let state_cookie = Cookie::build(STATE_COOKIE, state.into_secret())
+        .max_age(cookie::time::Duration::minutes(OIDC_FLOW_EXPIRATION_MIN))
+        .same_site(cookie::SameSite::Lax)
+        .path("/")
+        .http_only(true)
+        // .secure(true) // No dev https setup.
+        .finish();
+    set_cookie(response_options, &state_cookie)?;
+
+    leptos_actix::redirect(url.as_str());
+    Ok(())
+}
+
+/// Finish a flow when the provider sends the user back, returning the flow and the identity the
+/// provider vouched for. Each flow can only be finished once.
+pub async fn finish_oidc_flow(
+    app_state: &AppState,
+    request: &HttpRequest,
+    response_options: &ResponseOptions,
+    state: &str,
+    code: &str,
+) -> Result<(OidcFlow, VerifiedIdentity), ServerFnError> {
+    let expired = || ServerFnError::new("This login expired or was already used. Try again.");
+
+    if state.is_empty() || get_cookie(request, STATE_COOKIE) != state {
+        return Err(expired());
+    }
+    remove_cookie(response_options, STATE_COOKIE)?;
+
+    let flow = app_state
+        .valkey_pool
+        .getdel::<Option<String>, _>(key::oidc_flow(state))
+        .await?
+        .ok_or_else(expired)?;
+    let flow: OidcFlow = serde_json::from_str(&flow)
+        .or_else(|err| Err(ServerFnError::new(format!("Failed to read login: {err}"))))?;
+
+    let provider = app_state.oidc.provider(&flow.provider)?;
+    let client = app_state.oidc.client(provider, &app_state.site_url).await?;
+
+    let token_response = client
+        .exchange_code(AuthorizationCode::new(code.to_string()))
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "{} can't finish logins: {err}",
+                provider.name
+            )))
+        })?
+        .set_pkce_verifier(PkceCodeVerifier::new(flow.pkce_verifier.clone()))
+        .request_async(&app_state.oidc.http_client)
+        .await
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "{} didn't accept the login: {err}",
+                provider.name
+            )))
+        })?;
+    let id_token = token_response
+        .id_token()
+        .ok_or_else(|| ServerFnError::new(format!("{} didn't say who you are.", provider.name)))?;
+    let claims = id_token
+        .claims(&client.id_token_verifier(), &Nonce::new(flow.nonce.clone()))
+        .or_else(|err| {
+            Err(ServerFnError::new(format!(
+                "The login from {} couldn't be verified: {err}",
+                provider.name
+            )))
+        })?;
+
+    let identity = VerifiedIdentity {
+        issuer: claims.issuer().to_string(),
+        subject: claims.subject().to_string(),
+        email: claims.email().map(|email| email.to_string()),
+    };
+    Ok((flow, identity))
+}
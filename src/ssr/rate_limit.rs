@@ -0,0 +1,139 @@
+/// Sliding window rate limits, stored in Valkey.
+use crate::ssr::key;
+
+use fred::interfaces::{KeysInterface, SortedSetsInterface, TransactionInterface};
+use leptos::prelude::*;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    thread_rng,
+};
+
+/// A limit on how many times something can be done within a window of time.
+pub struct RateLimit {
+    /// Short name, used in the Valkey key.
+    pub name: &'static str,
+    /// Most actions allowed within any window.
+    pub max: i64,
+    pub window_sec: i64,
+}
+
+/// Login codes sent to a single email address.
+pub const LOGIN_CHALLENGE_PER_EMAIL: RateLimit = RateLimit {
+    name: "lgchal:email",
+    max: 5,
+    window_sec: 60 * 60,
+};
+
+/// Login codes requested from a single IP address.
+pub const LOGIN_CHALLENGE_PER_IP: RateLimit = RateLimit {
+    name: "lgchal:ip",
+    max: 20,
+    window_sec: 60 * 60,
+};
+
+/// Login code answers for a single email address.
+pub const LOGIN_ANSWER_PER_EMAIL: RateLimit = RateLimit {
+    name: "lgans:email",
+    max: 10,
+    window_sec: 20 * 60,
+};
+
+/// Login code answers from a single IP address.
+pub const LOGIN_ANSWER_PER_IP: RateLimit = RateLimit {
+    name: "lgans:ip",
+    max: 30,
+    window_sec: 20 * 60,
+};
+
+/// Verification codes sent for adding emails to a single account.
+pub const EMAIL_VERIFICATION_PER_ACCOUNT: RateLimit = RateLimit {
+    name: "emverify:acct",
+    max: 5,
+    window_sec: 60 * 60,
+};
+
+/// Username availability checks from a single IP address. Generous, since the register form checks
+/// as the user types.
+pub const USERNAME_CHECK_PER_IP: RateLimit = RateLimit {
+    name: "unamechk:ip",
+    max: 120,
+    window_sec: 10 * 60,
+};
+
+/// Passkey login attempts from a single IP address.
+pub const PASSKEY_LOGIN_PER_IP: RateLimit = RateLimit {
+    name: "pklogin:ip",
+    max: 30,
+    window_sec: 20 * 60,
+};
+
+/// Second factor answers for a single account. Wrong answers also end the login attempt, so this
+/// only matters when someone keeps getting new login codes.
+pub const SECOND_FACTOR_PER_ACCOUNT: RateLimit = RateLimit {
+    name: "2fa:acct",
+    max: 10,
+    window_sec: 20 * 60,
+};
+
+impl RateLimit {
+    /// Record an action by the given subject (e.g. an email or IP address), or fail with an error
+    /// saying when to try again if the limit has been reached. Rejected actions aren't recorded.
+    pub async fn check(
+        &self,
+        valkey_pool: &fred::clients::Pool,
+        subject: &str,
+    ) -> Result<(), ServerFnError> {
+        let key = key::rate_limit(self.name, subject);
+        let now = unix_time_millis();
+        let window_start = now - self.window_sec * 1000;
+
+        // Record the action and count the window in one step, so simultaneous actions can't all
+        // see the count from before any of them. Entries are named after their timestamp, with a
+        // random suffix so simultaneous actions are counted separately, so the oldest one tells
+        // when the window frees up without needing scores.
+        let entry = format!("{now}:{}", Alphanumeric.sample_string(&mut thread_rng(), 4));
+        let transaction = valkey_pool.multi();
+        let _ = transaction
+            .zremrangebyscore::<i64, _, _, _>(&key, "-inf", window_start as f64)
+            .await;
+        let _ = transaction
+            .zadd::<i64, _, _>(&key, None, None, false, false, (now as f64, entry.as_str()))
+            .await;
+        let _ = transaction
+            .expire::<i64, _>(&key, self.window_sec, None)
+            .await;
+        let _ = transaction
+            .zrange::<Vec<String>, _, _, _>(&key, 0, 0, None, false, None, false)
+            .await;
+        let _ = transaction.zcard::<i64, _>(&key).await;
+        let (_, _, _, oldest, count): (i64, i64, i64, Vec<String>, i64) =
+            transaction.exec(true).await?;
+
+        if count > self.max {
+            // Rejected actions don't count towards the limit.
+            valkey_pool.zrem::<i64, _, _>(&key, entry).await?;
+
+            let oldest = oldest
+                .first()
+                .and_then(|entry| entry.split(':').next())
+                .and_then(|timestamp| timestamp.parse::<i64>().ok())
+                .unwrap_or(now);
+            let minutes = ((oldest + self.window_sec * 1000 - now) / (60 * 1000) + 1).max(1);
+            return Err(ServerFnError::new(if minutes == 1 {
+                String::from("Too many attempts. Try again in 1 minute.")
+            } else {
+                format!("Too many attempts. Try again in {minutes} minutes.")
+            }));
+        }
+
+        Ok(())
+    }
+}
+
+/// Current Unix timestamp in milliseconds.
+fn unix_time_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or_default()
+}
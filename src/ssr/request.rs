@@ -0,0 +1,79 @@
+/// Helpers for getting information about the client making a request.
+use actix_web::http::header::USER_AGENT;
+use actix_web::{HttpRequest, web};
+use std::net::IpAddr;
+
+/// Longest user agent that will be kept, since it's entirely up to the client.
+const MAX_USER_AGENT_LEN: usize = 256;
+
+/// Reverse proxies whose X-Forwarded-For headers can be believed, from the `TRUSTED_PROXIES`
+/// env var, a comma separated list of IP addresses.
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+impl TrustedProxies {
+    pub fn from_env() -> Self {
+        let proxies = std::env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|proxy| !proxy.is_empty())
+            .map(|proxy| {
+                proxy
+                    .parse()
+                    .unwrap_or_else(|_| panic!("TRUSTED_PROXIES should list IP addresses: {proxy}"))
+            })
+            .collect();
+        TrustedProxies(proxies)
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.contains(ip)
+    }
+}
+
+/// Get the client's IP address.
+///
+/// This is the address the connection came from, unless it came from a trusted proxy. Then it's
+/// the last address in X-Forwarded-For that wasn't added by a trusted proxy. Earlier addresses
+/// are ignored, since the client can send any X-Forwarded-For it likes and proxies append to it.
+pub fn client_ip(request: &HttpRequest) -> String {
+    let Some(peer) = request.peer_addr().map(|addr| addr.ip()) else {
+        return String::new();
+    };
+    let Some(trusted) = request.app_data::<web::Data<TrustedProxies>>() else {
+        return peer.to_string();
+    };
+    if !trusted.contains(&peer) {
+        return peer.to_string();
+    }
+
+    let forwarded_hops = request
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    let client_hop = forwarded_hops
+        .into_iter()
+        .rev()
+        .map(|hop| hop.parse::<IpAddr>())
+        .find(|hop| !hop.as_ref().is_ok_and(|ip| trusted.contains(ip)));
+    match client_hop {
+        Some(Ok(ip)) => ip.to_string(),
+        // A garbled hop means the proxy isn't set up as expected, so don't guess past it.
+        Some(Err(_)) | None => peer.to_string(),
+    }
+}
+
+/// Get the client's user agent, truncated to a reasonable length.
+pub fn user_agent(request: &HttpRequest) -> String {
+    request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .chars()
+        .take(MAX_USER_AGENT_LEN)
+        .collect()
+}
@@ -0,0 +1,243 @@
+/// Authenticator app (TOTP) codes and recovery codes, the optional second factor for email login.
+use crate::ssr::app_state::{AppState, unix_time};
+use crate::ssr::rate_limit::SECOND_FACTOR_PER_ACCOUNT;
+use crate::ssr::uuid_codec::encode_uuid;
+
+use leptos::prelude::*;
+use qrcode::QrCode;
+use rand::{Rng, RngCore, thread_rng};
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+/// Seconds each code is valid for. Authenticator apps assume 30.
+const STEP_SEC: u64 = 30;
+pub const CODE_DIGITS: usize = 6;
+/// Bytes in a shared secret, the 160 bits RFC 4226 recommends.
+const SECRET_LEN: usize = 20;
+/// Codes this many steps before or after the current one still work, for clocks that are off.
+const ALLOWED_DRIFT_STEPS: i64 = 1;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Recovery codes leave out characters that are easy to mix up when written down.
+const RECOVERY_CODE_CHARS: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const RECOVERY_CODE_GROUPS: usize = 3;
+const RECOVERY_CODE_GROUP_LEN: usize = 4;
+
+/// Make a new random shared secret, encoded the way authenticator apps show it.
+pub fn generate_secret() -> String {
+    let mut secret = vec![0; SECRET_LEN];
+    thread_rng().fill_bytes(&mut secret);
+    Secret::Raw(secret).to_encoded().to_string()
+}
+
+/// Decode a shared secret from the way authenticator apps show it.
+pub fn decode_secret(secret: &str) -> Result<Vec<u8>, ServerFnError> {
+    Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .or_else(|err| Err(ServerFnError::new(format!("Invalid TOTP secret: {err}"))))
+}
+
+/// Code generator for a shared secret. The email is only a label for authenticator apps.
+fn totp(secret: Vec<u8>, email: &str) -> Result<TOTP, ServerFnError> {
+    TOTP::new(
+        Algorithm::SHA1,
+        CODE_DIGITS,
+        0,
+        STEP_SEC,
+        secret,
+        Some(String::from("Questarch")),
+        email.to_string(),
+    )
+    .or_else(|err| Err(ServerFnError::new(format!("Invalid TOTP setup: {err}"))))
+}
+
+/// QR code for authenticator apps to scan to set up a shared secret, as an SVG image.
+pub fn qr_code_svg(secret: Vec<u8>, email: &str) -> Result<String, ServerFnError> {
+    let code = QrCode::new(totp(secret, email)?.get_url()).or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to make TOTP QR code: {err}"
+        )))
+    })?;
+    Ok(code
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(200, 200)
+        .build())
+}
+
+/// Time step the code belongs to, if it's a correct code for the current time.
+pub fn matching_step(secret: Vec<u8>, code: &str) -> Result<Option<i64>, ServerFnError> {
+    let code = code.trim();
+    if code.len() != CODE_DIGITS || !code.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(None);
+    }
+
+    let totp = totp(secret, "")?;
+    let current = unix_time() / STEP_SEC as i64;
+    Ok(
+        (current - ALLOWED_DRIFT_STEPS..=current + ALLOWED_DRIFT_STEPS)
+            .find(|step| totp.check(code, *step as u64 * STEP_SEC)),
+    )
+}
+
+/// Recovery codes are compared ignoring case, spaces, and dashes.
+fn hash_recovery_code(code: &str) -> Vec<u8> {
+    let normalized: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    Sha256::digest(normalized.as_bytes()).to_vec()
+}
+
+/// Whether the account has an authenticator app set up.
+pub async fn has_second_factor(
+    app_state: &AppState,
+    account_id: Uuid,
+) -> Result<bool, ServerFnError> {
+    let (exists,) = sqlx::query_as::<_, (bool,)>(
+        r#"
+        select exists (select 1 from totp_credential where account_id = $1)
+        "#,
+    )
+    .bind(account_id)
+    .fetch_one(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get second factor from DB: {err}"
+        )))
+    })?;
+    Ok(exists)
+}
+
+/// Check a code from the account's authenticator app, or one of its recovery codes, using it up.
+/// Each code only works once, and accounts without a second factor never match.
+pub async fn check_second_factor(
+    app_state: &AppState,
+    account_id: Uuid,
+    code: &str,
+) -> Result<bool, ServerFnError> {
+    SECOND_FACTOR_PER_ACCOUNT
+        .check(&app_state.valkey_pool, &encode_uuid(account_id))
+        .await?;
+
+    let Some((secret,)) = sqlx::query_as::<_, (Vec<u8>,)>(
+        r#"
+        select secret
+        from totp_credential
+        where account_id = $1
+        "#,
+    )
+    .bind(account_id)
+    .fetch_optional(&app_state.db_pool)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Couldn't get second factor from DB: {err}"
+        )))
+    })?
+    else {
+        return Ok(false);
+    };
+
+    // Codes from the same step as the last one are refused, so a code seen over someone's
+    // shoulder can't be used again.
+    let used = if let Some(step) = matching_step(secret, code)? {
+        sqlx::query(
+            r#"
+            update totp_credential
+            set last_used_step = $2
+            where account_id = $1
+              and (last_used_step is null or last_used_step < $2)
+            "#,
+        )
+        .bind(account_id)
+        .bind(step)
+        .execute(&app_state.db_pool)
+        .await
+    } else {
+        sqlx::query(
+            r#"
+            update totp_recovery_code
+            set used_at = now()
+            where account_id = $1
+              and code_hash = $2
+              and used_at is null
+            "#,
+        )
+        .bind(account_id)
+        .bind(hash_recovery_code(code))
+        .execute(&app_state.db_pool)
+        .await
+    }
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to use second factor code: {err}"
+        )))
+    })?;
+
+    Ok(used.rows_affected() > 0)
+}
+
+/// Replace all of an account's recovery codes with new ones. Only hashes are stored, so the
+/// returned codes have to be shown to the user now or never.
+pub async fn replace_recovery_codes(
+    connection: &mut PgConnection,
+    account_id: Uuid,
+) -> Result<Vec<String>, ServerFnError> {
+    let mut rng = thread_rng();
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            (0..RECOVERY_CODE_GROUPS)
+                .map(|_| {
+                    (0..RECOVERY_CODE_GROUP_LEN)
+                        .map(|_| {
+                            RECOVERY_CODE_CHARS[rng.gen_range(0..RECOVERY_CODE_CHARS.len())] as char
+                        })
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect();
+
+    sqlx::query(
+        r#"
+        delete from totp_recovery_code
+        where account_id = $1
+        "#,
+    )
+    .bind(account_id)
+    .execute(&mut *connection)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to remove old recovery codes: {err}"
+        )))
+    })?;
+
+    sqlx::query(
+        r#"
+        insert into totp_recovery_code (account_id, code_hash)
+        select $1, unnest($2::bytea[])
+        "#,
+    )
+    .bind(account_id)
+    .bind(
+        codes
+            .iter()
+            .map(|code| hash_recovery_code(code))
+            .collect::<Vec<_>>(),
+    )
+    .execute(&mut *connection)
+    .await
+    .or_else(|err| {
+        Err(ServerFnError::new(format!(
+            "Failed to save recovery codes: {err}"
+        )))
+    })?;
+
+    Ok(codes)
+}
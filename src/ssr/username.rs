@@ -0,0 +1,202 @@
+/// Server side username rules.
+use crate::components::account_error::AccountError;
+use crate::components::username::{USERNAME_MAX_LEN, USERNAME_MIN_LEN};
+
+use rand::{Rng, thread_rng};
+use uuid::Uuid;
+
+/// How many free alternatives to suggest for a username that can't be used.
+pub const MAX_SUGGESTIONS: usize = 3;
+
+/// Check a username against the same rules as the `username` domain in the database. Doesn't check
+/// whether it's taken or allowed by UsernameRules.
+pub fn username_problem(username: &str) -> Option<String> {
+    if username.len() < USERNAME_MIN_LEN {
+        Some(format!(
+            "Usernames must be at least {USERNAME_MIN_LEN} characters long."
+        ))
+    } else if username.len() > USERNAME_MAX_LEN {
+        Some(format!(
+            "Usernames must be at most {USERNAME_MAX_LEN} characters long."
+        ))
+    } else if !username
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+    {
+        Some(String::from(
+            "Usernames may only have lowercase letters and numbers.",
+        ))
+    } else if !username.starts_with(|c: char| c.is_ascii_lowercase()) {
+        Some(String::from("Usernames must start with a letter."))
+    } else {
+        None
+    }
+}
+
+/// Usernames similar to the given one, most similar first. They all follow the username rules, but
+/// may be taken.
+pub fn suggestion_candidates(username: &str, rules: &UsernameRules) -> Vec<String> {
+    // Closest valid form of what was asked for.
+    let base: String = username
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .skip_while(|c| c.is_ascii_digit())
+        .take(USERNAME_MAX_LEN)
+        .collect();
+    let stem = base.trim_end_matches(|c: char| c.is_ascii_digit());
+    if stem.is_empty() {
+        return vec![];
+    }
+
+    let mut candidates = vec![base.clone(), stem.to_string()];
+    let mut rng = thread_rng();
+    let suffixes = (1..10)
+        .map(|n| n.to_string())
+        .chain((0..5).map(|_| rng.gen_range(10..1000).to_string()));
+    for suffix in suffixes {
+        let stem_len = stem.len().min(USERNAME_MAX_LEN - suffix.len());
+        candidates.push(format!("{}{suffix}", &stem[..stem_len]));
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    candidates.retain(|candidate| {
+        candidate != username
+            && username_problem(candidate).is_none()
+            && rules.problem(candidate).is_none()
+            && seen.insert(candidate.clone())
+    });
+    candidates
+}
+
+/// Words that can't be used as usernames, as configured in the `username_rule` table. They're kept
+/// as skeletons, so look-alikes match too.
+pub struct UsernameRules {
+    /// Usernames that are these words, optionally followed by digits, are reserved.
+    reserved: Vec<String>,
+    /// Usernames containing these words are blocked.
+    blocked: Vec<String>,
+}
+
+impl UsernameRules {
+    /// Get the current rules from the database.
+    pub async fn load(db_pool: &sqlx::postgres::PgPool) -> Result<Self, sqlx::Error> {
+        let mut rules = Self {
+            reserved: vec![],
+            blocked: vec![],
+        };
+        for (word, blocked) in sqlx::query_as::<_, (String, bool)>(
+            r#"
+            select word::text, blocked
+            from username_rule
+            "#,
+        )
+        .fetch_all(db_pool)
+        .await?
+        {
+            if blocked {
+                rules.blocked.push(skeleton(&word));
+            } else {
+                rules.reserved.push(skeleton(&word));
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Check whether a username is reserved or blocked, and why.
+    pub fn problem(&self, username: &str) -> Option<AccountError> {
+        let full = skeleton(username);
+        let without_digits = skeleton(username.trim_end_matches(|c: char| c.is_ascii_digit()));
+        if self.blocked.iter().any(|word| full.contains(word.as_str())) {
+            Some(AccountError::UsernameNotAllowed)
+        } else if self
+            .reserved
+            .iter()
+            .any(|word| *word == full || *word == without_digits)
+        {
+            Some(AccountError::UsernameReserved)
+        } else {
+            None
+        }
+    }
+}
+
+/// Make sure a username someone wants to take isn't reserved or blocked, unless they're taking it
+/// for an admin account.
+pub async fn check_username_allowed(
+    db_pool: &sqlx::postgres::PgPool,
+    username: &str,
+    account_id: Option<Uuid>,
+) -> Result<(), AccountError> {
+    let Some(problem) = UsernameRules::load(db_pool)
+        .await
+        .or_else(|err| Err(AccountError::from_db(err, "Couldn't get username rules")))?
+        .problem(username)
+    else {
+        return Ok(());
+    };
+
+    if let Some(account_id) = account_id {
+        let (is_admin,) = sqlx::query_as::<_, (bool,)>(
+            r#"
+            select is_admin
+            from account
+            where id = $1
+            "#,
+        )
+        .bind(account_id)
+        .fetch_one(db_pool)
+        .await
+        .or_else(|err| Err(AccountError::from_db(err, "Couldn't get account")))?;
+        if is_admin {
+            return Ok(());
+        }
+    }
+
+    Err(problem)
+}
+
+/// Reduce a username to what it looks like, so look-alikes such as "4dm1n", "adrnin" and "admin"
+/// all have the same skeleton.
+fn skeleton(username: &str) -> String {
+    username
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            '0' => 'o',
+            '1' | 'l' => 'i',
+            '2' => 'z',
+            '3' => 'e',
+            '4' => 'a',
+            '5' => 's',
+            '6' | '9' => 'g',
+            '7' => 't',
+            '8' => 'b',
+            c => c,
+        })
+        .collect::<String>()
+        .replace("rn", "m")
+        .replace("vv", "w")
+}
+
+/// Find the current username of the profile that most recently had the given one, so links using
+/// an old username can redirect to it. None if no profile ever had it, or if a profile has it now.
+pub async fn find_renamed_profile(
+    db_pool: &sqlx::postgres::PgPool,
+    old_username: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar::<_, String>(
+        r#"
+        select profile.username::text
+        from
+          profile_username_history
+          join profile on profile_username_history.profile_id = profile.id
+        where profile_username_history.username = $1
+          and not exists(select 1 from profile where username = $1)
+        order by profile_username_history.changed_at desc
+        limit 1
+        "#,
+    )
+    .bind(old_username)
+    .fetch_optional(db_pool)
+    .await
+}